@@ -0,0 +1,227 @@
+use crate::report::SkipReason;
+use flate2::read::MultiGzDecoder;
+use std::io::{Cursor, Read};
+
+/// Joins an archive's path and the path of an entry inside it into the virtual path
+/// findings report, e.g. `dist/app.jar!/config/application.properties`.
+pub const ENTRY_SEPARATOR: &str = "!/";
+
+/// outer_path is the on-disk file a finding's path points into: `dist/app.jar` for
+/// `dist/app.jar!/config/application.properties`, and the path itself outside archives.
+pub fn outer_path(path: &str) -> &str {
+    path.split_once(ENTRY_SEPARATOR)
+        .map_or(path, |(outer, _)| outer)
+}
+
+/// How many archives deep the scanner looks by default: an archive, an archive inside it,
+/// and one more level, enough for a `.war` holding `.jar` libraries.
+pub const DEFAULT_MAX_DEPTH: usize = 3;
+
+/// Decompressed bytes extracted from one archive, nested archives included, by default.
+pub const DEFAULT_MAX_TOTAL_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Largest ratio of decompressed to compressed size accepted for an entry by default.
+pub const DEFAULT_MAX_RATIO: u64 = 100;
+
+/// Entries up to this size are never rejected for their compression ratio, since small
+/// repetitive text files legitimately compress very well.
+const RATIO_FLOOR: u64 = 1024 * 1024;
+
+/// Limits on archive traversal, which keep zip bombs from exhausting memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveLimits {
+    /// How many archives deep to look; 0 leaves archives unopened.
+    pub max_depth: usize,
+    /// Decompressed bytes extracted from one archive, including archives nested in it.
+    pub max_total_size: u64,
+    /// Largest ratio of decompressed to compressed size accepted for one entry.
+    pub max_ratio: u64,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_total_size: DEFAULT_MAX_TOTAL_SIZE,
+            max_ratio: DEFAULT_MAX_RATIO,
+        }
+    }
+}
+
+/// An archive format the scanner can open. Formats are recognized by content, so `.jar`,
+/// `.whl`, `.nupkg` and other zip-based packages need no special casing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    /// Gzip-compressed content: a `.tar.gz` is opened as the tar inside it, anything else
+    /// as a single entry.
+    Gzip,
+}
+
+impl ArchiveFormat {
+    /// detect recognizes an archive from its magic bytes.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+            Some(Self::Zip)
+        } else if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::Gzip)
+        } else if is_tar(bytes) {
+            Some(Self::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// A regular file inside an archive.
+#[derive(Debug)]
+pub struct ArchiveEntry {
+    /// The entry's path inside the archive.
+    pub name: String,
+    /// The decompressed content, or why the entry was not extracted.
+    pub content: Result<Vec<u8>, SkipReason>,
+}
+
+/// extract_entries lists the regular files in archive `bytes`, read from the file `name`.
+/// Decompressed bytes are taken from `remaining`, shared by an archive and everything
+/// nested in it. Once it runs out, the entry being read fails with `ArchiveTooLarge` and
+/// extraction stops; an entry exceeding the ratio limit fails with `CompressionRatio`.
+/// Content that cannot be opened as an archive at all is `Unreadable`.
+pub fn extract_entries(
+    name: &str,
+    bytes: &[u8],
+    limits: &ArchiveLimits,
+    remaining: &mut u64,
+) -> Result<Vec<ArchiveEntry>, SkipReason> {
+    match ArchiveFormat::detect(bytes) {
+        Some(ArchiveFormat::Zip) => zip_entries(bytes, limits, remaining),
+        Some(ArchiveFormat::Tar) => tar_entries(bytes),
+        Some(ArchiveFormat::Gzip) => {
+            let decoder = MultiGzDecoder::new(bytes);
+            let content = read_limited(decoder, bytes.len() as u64, limits, remaining)?;
+            if is_tar(&content) {
+                return tar_entries(&content);
+            }
+            Ok(vec![ArchiveEntry {
+                name: gunzipped_name(name),
+                content: Ok(content),
+            }])
+        }
+        None => Err(SkipReason::Unreadable),
+    }
+}
+
+fn zip_entries(
+    bytes: &[u8],
+    limits: &ArchiveLimits,
+    remaining: &mut u64,
+) -> Result<Vec<ArchiveEntry>, SkipReason> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(bytes)).map_err(|_| SkipReason::Unreadable)?;
+    let mut entries = Vec::new();
+    for index in 0..archive.len() {
+        let name = archive
+            .name_for_index(index)
+            .unwrap_or_default()
+            .to_string();
+        // Unsupported compression methods and encrypted entries cannot be read.
+        let Ok(file) = archive.by_index(index) else {
+            entries.push(ArchiveEntry {
+                name,
+                content: Err(SkipReason::Unreadable),
+            });
+            continue;
+        };
+        if !file.is_file() {
+            continue;
+        }
+        let compressed = file.compressed_size();
+        let content = read_limited(file, compressed, limits, remaining);
+        let exhausted = content == Err(SkipReason::ArchiveTooLarge);
+        entries.push(ArchiveEntry { name, content });
+        if exhausted {
+            break;
+        }
+    }
+    Ok(entries)
+}
+
+/// tar_entries lists the regular files of an uncompressed tar. Its content is already in
+/// memory, so copying it out does not count against the decompression limits.
+fn tar_entries(bytes: &[u8]) -> Result<Vec<ArchiveEntry>, SkipReason> {
+    let mut archive = tar::Archive::new(bytes);
+    let mut entries = Vec::new();
+    for entry in archive.entries().map_err(|_| SkipReason::Unreadable)? {
+        // A damaged header ends the listing; entries before it are still scanned.
+        let Ok(mut entry) = entry else {
+            if entries.is_empty() {
+                return Err(SkipReason::Unreadable);
+            }
+            break;
+        };
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path().map_or_else(
+            |_| String::from_utf8_lossy(&entry.path_bytes()).into_owned(),
+            |path| path.to_string_lossy().into_owned(),
+        );
+        let mut content = Vec::new();
+        let content = entry
+            .read_to_end(&mut content)
+            .map(|_| content)
+            .map_err(|_| SkipReason::Unreadable);
+        entries.push(ArchiveEntry {
+            name: name.trim_start_matches("./").to_string(),
+            content,
+        });
+    }
+    Ok(entries)
+}
+
+/// read_limited decompresses `reader`, which reads `compressed` bytes of input, within
+/// both the remaining total size and the ratio limit, without ever holding more than the
+/// smaller of the two in memory.
+fn read_limited(
+    reader: impl Read,
+    compressed: u64,
+    limits: &ArchiveLimits,
+    remaining: &mut u64,
+) -> Result<Vec<u8>, SkipReason> {
+    let ratio_cap = compressed.saturating_mul(limits.max_ratio).max(RATIO_FLOOR);
+    let cap = ratio_cap.min(*remaining);
+
+    let mut content = Vec::new();
+    reader
+        .take(cap.saturating_add(1))
+        .read_to_end(&mut content)
+        .map_err(|_| SkipReason::Unreadable)?;
+    let read = content.len() as u64;
+    let over_total = read > *remaining;
+    *remaining = remaining.saturating_sub(read);
+    if over_total {
+        Err(SkipReason::ArchiveTooLarge)
+    } else if read > cap {
+        Err(SkipReason::CompressionRatio)
+    } else {
+        Ok(content)
+    }
+}
+
+/// is_tar checks for the POSIX or GNU magic in the first tar header.
+fn is_tar(bytes: &[u8]) -> bool {
+    bytes
+        .get(257..265)
+        .is_some_and(|magic| magic.starts_with(b"ustar\0") || magic == b"ustar  \0")
+}
+
+/// gunzipped_name names the single entry of a gzip file after the file: `app.log.gz`
+/// holds `app.log`.
+fn gunzipped_name(name: &str) -> String {
+    let file_name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    file_name
+        .strip_suffix(".gz")
+        .unwrap_or(file_name)
+        .to_string()
+}
@@ -0,0 +1,72 @@
+/// Shortest run of printable characters extracted from binary files, like `strings -n 8`.
+pub const MIN_STRING_LEN: usize = 8;
+
+/// A run of printable text inside binary content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintableString {
+    /// Byte offset of the first character in the binary content.
+    pub offset: usize,
+    pub text: String,
+    /// Whether the run was stored as UTF-16LE, two bytes per character.
+    pub wide: bool,
+}
+
+impl PrintableString {
+    /// byte_offset maps a character index in `text` to a byte offset in the content.
+    pub fn byte_offset(&self, index: usize) -> usize {
+        self.offset + if self.wide { index * 2 } else { index }
+    }
+}
+
+/// extract_strings finds runs of at least `min_len` printable ASCII characters, stored
+/// either as single bytes or as UTF-16LE, ordered by offset. Tabs count as printable;
+/// other control characters, including line breaks, end a run.
+pub fn extract_strings(bytes: &[u8], min_len: usize) -> Vec<PrintableString> {
+    let mut strings = Vec::new();
+    collect_runs(bytes, 1, 0, min_len, &mut strings);
+    // A UTF-16 string can start at an even or an odd offset.
+    collect_runs(bytes, 2, 0, min_len, &mut strings);
+    collect_runs(bytes, 2, 1, min_len, &mut strings);
+    strings.sort_by_key(|string| string.offset);
+    strings
+}
+
+fn is_printable(byte: u8) -> bool {
+    byte == b'\t' || (0x20..0x7f).contains(&byte)
+}
+
+/// collect_runs walks `bytes` from `start` in units of `width` bytes. A unit is a printable
+/// character when its first byte is printable and any further bytes are zero.
+fn collect_runs(
+    bytes: &[u8],
+    width: usize,
+    start: usize,
+    min_len: usize,
+    strings: &mut Vec<PrintableString>,
+) {
+    let mut run_start = start;
+    let mut text = String::new();
+    let mut flush = |run_start: usize, text: &mut String| {
+        if text.len() >= min_len {
+            strings.push(PrintableString {
+                offset: run_start,
+                text: std::mem::take(text),
+                wide: width > 1,
+            });
+        }
+        text.clear();
+    };
+
+    let units = bytes.get(start..).unwrap_or_default().chunks_exact(width);
+    for (index, unit) in units.enumerate() {
+        if is_printable(unit[0]) && unit[1..].iter().all(|&byte| byte == 0) {
+            if text.is_empty() {
+                run_start = start + index * width;
+            }
+            text.push(unit[0] as char);
+        } else {
+            flush(run_start, &mut text);
+        }
+    }
+    flush(run_start, &mut text);
+}
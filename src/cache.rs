@@ -0,0 +1,226 @@
+use crate::detector::{Match, MatchLocator};
+use crate::error::{self, Error};
+use crate::report::CacheStats;
+use crate::scanner::Scanner;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever the cache file layout changes, so older files are ignored.
+const CACHE_FORMAT: u32 = 2;
+
+/// default_dir returns the user cache directory for scan caches, e.g. `~/.cache/keywatch`.
+pub fn default_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("keywatch"))
+}
+
+/// ruleset_hash identifies everything that decides what `scanner` matches: each
+/// detector's fingerprint and the KeyWatch version. Returns None when a detector has no
+/// fingerprint, since its results could change without the hash noticing.
+pub fn ruleset_hash(scanner: &Scanner) -> Option<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    for detector in scanner.detectors().iter() {
+        hasher.update([0]);
+        hasher.update(detector.fingerprint()?);
+    }
+    Some(hex_digest(hasher))
+}
+
+/// content_hash hashes file content for cache lookups.
+pub fn content_hash(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    hex_digest(hasher)
+}
+
+fn hex_digest(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// A match remembered for one detector. Only its position is stored, never the matched
+/// text, so the cache holds no copies of the secrets it describes.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CachedMatch {
+    pub detector: String,
+    pub line_number: usize,
+    /// Byte offset of the match in the decoded file content.
+    pub offset: usize,
+    /// Length of the match in bytes.
+    pub length: usize,
+}
+
+impl CachedMatch {
+    /// matched_content reads the match back from `content`, the file it was found in.
+    pub fn matched_content<'a>(&self, content: &'a str) -> Option<&'a str> {
+        content.get(self.offset..self.offset.checked_add(self.length)?)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    content_hash: String,
+    roots: Vec<Option<String>>,
+    matches: Vec<CachedMatch>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CacheFile {
+    format: u32,
+    ruleset: String,
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+/// The detector matches of previously scanned files, valid for one ruleset. Files whose
+/// content hash is unchanged are not scanned again.
+pub struct ScanCache {
+    file: PathBuf,
+    contents: CacheFile,
+    stats: CacheStats,
+}
+
+impl ScanCache {
+    /// open loads the cache for the current directory from `dir`. A missing or unreadable
+    /// cache, or one written for another ruleset, starts out empty.
+    pub fn open(dir: &Path, ruleset: String) -> ScanCache {
+        let work_dir = std::env::current_dir()
+            .and_then(fs::canonicalize)
+            .unwrap_or_default();
+        let key = content_hash(work_dir.to_string_lossy().as_bytes());
+        let file = dir.join(format!("scan-{}.json", &key[..16]));
+
+        let contents = fs::read(&file)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<CacheFile>(&bytes).ok())
+            .filter(|cached| cached.format == CACHE_FORMAT && cached.ruleset == ruleset)
+            .unwrap_or(CacheFile {
+                format: CACHE_FORMAT,
+                ruleset,
+                entries: BTreeMap::new(),
+            });
+
+        ScanCache {
+            file,
+            contents,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// lookup returns the matches stored for `path` when its content and scan roots are
+    /// unchanged, and counts the hit or miss.
+    pub fn lookup(
+        &mut self,
+        path: &str,
+        roots: &[Option<String>],
+        content_hash: &str,
+    ) -> Option<&[CachedMatch]> {
+        let entry = self
+            .contents
+            .entries
+            .get(path)
+            .filter(|entry| entry.content_hash == content_hash && entry.roots == roots);
+        match entry {
+            Some(entry) => {
+                self.stats.hits += 1;
+                Some(&entry.matches)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// store records the matches of a freshly scanned file by their position in
+    /// `content`. A file with a match that cannot be located in it is not stored.
+    pub fn store(
+        &mut self,
+        path: &str,
+        roots: &[Option<String>],
+        content_hash: String,
+        content: &str,
+        matches: Vec<(String, Match)>,
+    ) {
+        let mut locator = MatchLocator::new(content);
+        let mut cached = Vec::with_capacity(matches.len());
+        for (detector, found) in &matches {
+            let Some(offset) = locator.locate(detector, found) else {
+                return;
+            };
+            cached.push(CachedMatch {
+                detector: detector.clone(),
+                line_number: found.line_number,
+                offset,
+                length: found.matched_content.len(),
+            });
+        }
+        self.contents.entries.insert(
+            path.to_string(),
+            CacheEntry {
+                content_hash,
+                roots: roots.to_vec(),
+                matches: cached,
+            },
+        );
+    }
+
+    /// stats returns the hits and misses since the cache was opened.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// save writes the cache back, dropping entries for files that no longer exist. The
+    /// file is replaced atomically, so a concurrent scan never reads half of it.
+    pub fn save(&mut self) -> error::Result<()> {
+        self.contents
+            .entries
+            .retain(|path, _| Path::new(path).is_file());
+
+        let io_error = |source| Error::Io {
+            path: self.file.clone(),
+            source,
+        };
+        if let Some(dir) = self.file.parent() {
+            create_private_dir(dir).map_err(io_error)?;
+        }
+        let json = serde_json::to_vec(&self.contents).map_err(|err| io_error(err.into()))?;
+        let temp = self
+            .file
+            .with_extension(format!("tmp{}", std::process::id()));
+        write_private_file(&temp, &json).map_err(io_error)?;
+        fs::rename(&temp, &self.file).map_err(io_error)
+    }
+}
+
+/// create_private_dir creates `dir` and its missing parents readable only by the user.
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+/// write_private_file writes `bytes` to a new file readable only by the user, replacing
+/// any file left behind at `path`.
+fn write_private_file(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let _ = fs::remove_file(path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(bytes)
+}
@@ -1,3 +1,7 @@
+use crate::archive;
+use crate::config::DetectorSelection;
+use crate::policy::{FailurePolicy, parse_finding_budget};
+use crate::report::Severity;
 use clap::{Args, Parser, Subcommand, ValueEnum};
 
 /// KeyWatch: A secret scanner for your files and directories.
@@ -12,6 +16,7 @@ impl CliOptions {
     pub fn validate(&self) -> Result<(), String> {
         match &self.command {
             Command::Hook(args) => args.validate(),
+            Command::Redact(args) => args.validate(),
             _ => Ok(()),
         }
     }
@@ -22,9 +27,33 @@ pub enum Command {
     /// Scan files or directories
     Scan(ScanArgs),
 
+    /// Scan a container image saved with `docker save`, or an OCI layout directory
+    ScanImage(ScanImageArgs),
+
+    /// Scan paths, then rescan changed files until interrupted
+    Watch(WatchArgs),
+
+    /// Copy input to stdout with detected secrets replaced
+    Redact(RedactArgs),
+
+    /// Run a Language Server Protocol server on stdio for editor diagnostics
+    Lsp(LspArgs),
+
+    /// Run a command, masking secrets in its stdout and stderr
+    Exec(ExecArgs),
+
+    /// Serve an HTTP API that scans request bodies
+    Serve(ServeArgs),
+
     /// Manage KeyWatch git hooks
     Hook(HookArgs),
 
+    /// Inspect and test detector rules
+    Rules(RulesArgs),
+
+    /// Manage trust for repository-local detectors.toml files
+    Config(ConfigArgs),
+
     /// Print shell aliases for keywatch and kw
     Init {
         #[arg(value_enum)]
@@ -37,7 +66,7 @@ pub enum Command {
 
 #[derive(Args, Debug)]
 pub struct ScanArgs {
-    /// Paths to scan (files or directories)
+    /// Paths to scan (files or directories), or `-` to read standard input
     #[arg(required = true)]
     pub paths: Vec<String>,
 
@@ -53,9 +82,373 @@ pub struct ScanArgs {
     #[arg(long)]
     pub exclude: Option<String>,
 
+    #[command(flatten)]
+    pub policy: PolicyArgs,
+
+    /// Detector profile to run, e.g. secrets, pii or all (profiles can be defined in detectors.toml)
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Only run detectors with one of these tags (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    pub only_tags: Vec<String>,
+
+    /// Skip detectors with any of these tags (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    pub skip_tags: Vec<String>,
+
+    /// Only run these detectors, by rule ID or name (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    pub only_rules: Vec<String>,
+
+    /// Skip these detectors, by rule ID or name (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    pub skip_rules: Vec<String>,
+
+    /// Name to report for standard input and to match path-scoped detectors against
+    #[arg(long)]
+    pub stdin_filename: Option<String>,
+
+    /// Scan printable strings in binary files instead of skipping them
+    #[arg(long, default_value_t = false)]
+    pub scan_binaries: bool,
+
+    /// Skip files larger than this many bytes
+    #[arg(long)]
+    pub max_file_size: Option<u64>,
+
+    /// Open archives nested up to this many levels deep (0 scans archives as binary files)
+    #[arg(long, default_value_t = archive::DEFAULT_MAX_DEPTH)]
+    pub archive_depth: usize,
+
+    /// Stop extracting an archive after this many decompressed bytes, nested archives included
+    #[arg(long, default_value_t = archive::DEFAULT_MAX_TOTAL_SIZE)]
+    pub max_archive_size: u64,
+
+    /// Skip archive entries that decompress to more than this many times their compressed size
+    #[arg(long, default_value_t = archive::DEFAULT_MAX_RATIO)]
+    pub max_compression_ratio: u64,
+
+    /// Reuse results for files unchanged since the last cached scan
+    #[arg(long, default_value_t = false)]
+    pub cache: bool,
+
+    /// Directory holding the scan cache (default: the user cache directory)
+    #[arg(long, requires = "cache")]
+    pub cache_dir: Option<String>,
+}
+
+impl ScanArgs {
+    /// reads_stdin reports whether `-` was given as a path.
+    pub fn reads_stdin(&self) -> bool {
+        self.paths.iter().any(|path| path == "-")
+    }
+
+    /// detector_filters collects the tag and rule filters applied on top of `--profile`.
+    pub fn detector_filters(&self) -> DetectorSelection {
+        DetectorSelection {
+            only_tags: self.only_tags.clone(),
+            skip_tags: self.skip_tags.clone(),
+            only_rules: self.only_rules.clone(),
+            skip_rules: self.skip_rules.clone(),
+        }
+    }
+}
+
+/// Options deciding when a scan fails and with which exit code, shared by `scan` and
+/// `scan-image`.
+#[derive(Args, Debug)]
+pub struct PolicyArgs {
     /// Exit code behavior
     #[arg(long, value_enum, default_value_t = ExitMode::Strict)]
     pub exit_mode: ExitMode,
+
+    /// Fail on findings at or above this severity (overrides --exit-mode)
+    #[arg(long, conflicts_with = "exit_mode")]
+    pub fail_on: Option<Severity>,
+
+    /// Findings allowed per severity before failing, e.g. LOW=5 (comma-separated or repeated)
+    #[arg(long, value_delimiter = ',', value_parser = parse_finding_budget)]
+    pub max_findings: Vec<(Severity, usize)>,
+
+    /// Exit with a code identifying the highest severity found instead of 1
+    #[arg(long, default_value_t = false)]
+    pub severity_exit_codes: bool,
+
+    /// Fail the scan when any file was skipped, e.g. unreadable or binary
+    #[arg(long, default_value_t = false)]
+    pub fail_on_skipped: bool,
+}
+
+impl PolicyArgs {
+    pub fn failure_policy(&self) -> FailurePolicy {
+        let mut policy = match self.fail_on {
+            Some(severity) => FailurePolicy {
+                threshold: Some(severity),
+                ..FailurePolicy::default()
+            },
+            None => FailurePolicy::from_exit_mode(&self.exit_mode),
+        };
+        policy.budgets.extend(self.max_findings.iter().copied());
+        policy.severity_exit_codes = self.severity_exit_codes;
+        policy
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ScanImageArgs {
+    /// Tarball written by `docker save`, or OCI image layout directory
+    pub image: String,
+
+    /// Output the result to a file
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Print the scan results to the console as JSON
+    #[arg(short, long, default_value_t = false)]
+    pub verbose: bool,
+
+    #[command(flatten)]
+    pub policy: PolicyArgs,
+
+    /// Detector profile to run, e.g. secrets, pii or all
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Skip layer files larger than this many bytes
+    #[arg(long)]
+    pub max_file_size: Option<u64>,
+
+    /// Pass even when a whole layer could not be scanned, e.g. because it is zstd-compressed
+    #[arg(long, default_value_t = false)]
+    pub allow_unscanned_layers: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct RulesArgs {
+    #[command(subcommand)]
+    pub action: RulesAction,
+}
+
+#[derive(Args, Debug)]
+pub struct WatchArgs {
+    /// Paths to watch (files or directories)
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+
+    /// Paths to exclude from scanning (comma-separated, supports glob patterns)
+    #[arg(long)]
+    pub exclude: Option<String>,
+
+    /// Detector profile to run, e.g. secrets, pii or all
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Milliseconds without file events before changed files are rescanned
+    #[arg(long, default_value_t = 200)]
+    pub debounce: u64,
+
+    /// Poll for changes instead of using filesystem notifications
+    #[arg(long, default_value_t = false)]
+    pub poll: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct LspArgs {
+    /// Detector profile to run, e.g. secrets, pii or all
+    #[arg(long)]
+    pub profile: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on; only loopback is reachable unless you choose otherwise
+    #[arg(long, default_value = crate::serve::DEFAULT_LISTEN)]
+    pub listen: String,
+
+    /// Largest request body accepted, in bytes
+    #[arg(long, default_value_t = crate::serve::DEFAULT_MAX_BODY_BYTES)]
+    pub max_body_bytes: usize,
+
+    /// Requests handled at once; further connections get 503
+    #[arg(long, default_value_t = crate::serve::DEFAULT_MAX_CONNECTIONS)]
+    pub max_connections: usize,
+
+    /// File holding the bearer token clients must send; defaults to $KEYWATCH_SERVE_TOKEN
+    #[arg(long)]
+    pub token_file: Option<String>,
+
+    /// Detector profile to run, e.g. secrets, pii or all
+    #[arg(long)]
+    pub profile: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct RedactArgs {
+    /// Files to redact; reads standard input when empty or `-`
+    pub paths: Vec<String>,
+
+    /// Rewrite the files instead of printing them, keeping a backup of each changed file
+    #[arg(long, default_value_t = false)]
+    pub in_place: bool,
+
+    /// Suffix for backups written by --in-place
+    #[arg(long, default_value = ".bak", requires = "in_place")]
+    pub backup_suffix: String,
+
+    /// Replace secrets with this text instead of a [REDACTED:<finding type>] token
+    #[arg(long)]
+    pub mask: Option<String>,
+
+    /// Detector profile to redact with, e.g. secrets, pii or all
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Name for standard input, matched against path-scoped detectors
+    #[arg(long)]
+    pub stdin_filename: Option<String>,
+}
+
+impl RedactArgs {
+    pub fn validate(&self) -> Result<(), String> {
+        let reads_stdin = self.paths.is_empty() || self.paths.iter().any(|path| path == "-");
+        if reads_stdin && self.in_place {
+            return Err(
+                "--in-place needs file paths; standard input cannot be rewritten".to_string(),
+            );
+        }
+        if reads_stdin && self.paths.len() > 1 {
+            return Err("`-` (standard input) cannot be combined with other paths".to_string());
+        }
+        if !reads_stdin && self.stdin_filename.is_some() {
+            return Err("--stdin-filename only applies when redacting standard input".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ExecArgs {
+    /// Command to run, after `--`
+    #[arg(last = true, required = true)]
+    pub command: Vec<String>,
+
+    /// Exit with 1 if any secret was masked, even when the command succeeded
+    #[arg(long, default_value_t = false)]
+    pub fail_on_leak: bool,
+
+    /// Replace secrets with this text instead of a [REDACTED:<finding type>] token
+    #[arg(long)]
+    pub mask: Option<String>,
+
+    /// Detector profile to mask with, e.g. secrets, pii or all
+    #[arg(long)]
+    pub profile: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RulesAction {
+    /// Run each detector against its positive and negative examples
+    Test(RulesTestArgs),
+
+    /// Check detectors.toml for broken, overbroad and duplicate rules
+    Lint(RulesLintArgs),
+
+    /// List the active detectors after merging every configuration layer
+    List(RulesListArgs),
+
+    /// Convert rules from another scanner into detectors.toml format
+    Import(RulesImportArgs),
+
+    /// Show a detector's metadata, pattern and remediation guidance
+    Show(RulesShowArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct RulesTestArgs {
+    /// Detector file to test instead of the active detectors.toml
+    #[arg(long)]
+    pub config: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct RulesLintArgs {
+    /// Detector file to lint instead of the active detectors.toml
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// Exit with an error when only warnings are found
+    #[arg(long, default_value_t = false)]
+    pub deny_warnings: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct RulesShowArgs {
+    /// Rule ID (e.g. KW0001) or detector name
+    pub id: String,
+}
+
+#[derive(Args, Debug)]
+pub struct RulesImportArgs {
+    /// Format of the rule file to import
+    #[arg(long, value_enum)]
+    pub from: RuleFormat,
+
+    /// Rule file to import
+    pub file: String,
+
+    /// Severity given to every imported detector
+    #[arg(long, default_value = "HIGH")]
+    pub severity: Severity,
+
+    /// Write the detectors to a file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum RuleFormat {
+    Gitleaks,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub action: ConfigAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Allow a project detectors.toml to disable detectors and lower severities
+    Trust(ConfigTrustArgs),
+
+    /// Remove every trust record for a project detectors.toml
+    Untrust(ConfigUntrustArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigTrustArgs {
+    /// Config file, or the directory containing detectors.toml
+    #[arg(default_value = ".")]
+    pub path: String,
+
+    /// Trust only the current contents of the file (and what it extends), not the directory
+    #[arg(long)]
+    pub hash: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigUntrustArgs {
+    /// Config file, or the directory containing detectors.toml
+    #[arg(default_value = ".")]
+    pub path: String,
+}
+
+#[derive(Args, Debug)]
+pub struct RulesListArgs {
+    /// Show which layer defined, overrode or disabled each detector
+    #[arg(long)]
+    pub explain: bool,
 }
 
 #[derive(Args, Debug)]
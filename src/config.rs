@@ -0,0 +1,540 @@
+use crate::detector::{DEFAULT_DETECTORS, DetectorExamples, RegexDetector, compile_path_patterns};
+use crate::report::{DetectorMetadata, Severity};
+use crate::trust;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The layers detector configuration is merged from, in increasing order of precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigLayer {
+    BuiltIn,
+    Install,
+    User,
+    Project,
+    Env,
+    /// A file passed explicitly, e.g. `rules test --config`.
+    File,
+}
+
+impl ConfigLayer {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BuiltIn => "built-in",
+            Self::Install => "install",
+            Self::User => "user",
+            Self::Project => "project",
+            Self::Env => "env",
+            Self::File => "file",
+        }
+    }
+}
+
+/// A configuration file belonging to one layer.
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    pub layer: ConfigLayer,
+    pub path: PathBuf,
+    /// Untrusted sources may only add detectors or raise severities.
+    pub trusted: bool,
+}
+
+impl ConfigSource {
+    pub fn label(&self) -> String {
+        let trust = if self.trusted { "" } else { " (untrusted)" };
+        format!("{} {}{}", self.layer.as_str(), self.path.display(), trust)
+    }
+}
+
+/// This structure mirrors the detectors.toml file layout.
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    extends: Vec<String>,
+    #[serde(default)]
+    disable: Vec<String>,
+    #[serde(default)]
+    detectors: Vec<DetectorEntry>,
+    #[serde(default)]
+    profiles: BTreeMap<String, DetectorSelection>,
+}
+
+/// A `[[detectors]]` entry. Only `name` is required when it overrides an existing detector.
+#[derive(Deserialize, Clone, PartialEq)]
+struct DetectorEntry {
+    name: String,
+    id: Option<String>,
+    pattern: Option<String>,
+    finding_type: Option<String>,
+    severity: Option<String>,
+    tags: Option<Vec<String>>,
+    description: Option<String>,
+    remediation: Option<String>,
+    references: Option<Vec<String>>,
+    examples: Option<DetectorExamples>,
+    paths: Option<Vec<String>>,
+    exclude_paths: Option<Vec<String>>,
+}
+
+impl DetectorEntry {
+    fn apply_override(&mut self, other: DetectorEntry) {
+        macro_rules! take {
+            ($($field:ident),*) => {
+                $(if other.$field.is_some() {
+                    self.$field = other.$field;
+                })*
+            };
+        }
+        take!(
+            id,
+            pattern,
+            finding_type,
+            severity,
+            tags,
+            description,
+            remediation,
+            references,
+            examples,
+            paths,
+            exclude_paths
+        );
+    }
+
+    /// restrict_override strips everything from an untrusted override except a severity
+    /// increase, returning the names of the fields it dropped.
+    fn restrict_override(&self, other: &mut DetectorEntry) -> Vec<&'static str> {
+        let mut dropped = Vec::new();
+        macro_rules! drop_changed {
+            ($($field:ident),*) => {
+                $(if other.$field.is_some() && other.$field != self.$field {
+                    dropped.push(stringify!($field));
+                }
+                other.$field = None;)*
+            };
+        }
+        drop_changed!(
+            id,
+            pattern,
+            finding_type,
+            tags,
+            description,
+            remediation,
+            references,
+            examples,
+            paths,
+            exclude_paths
+        );
+
+        let current = self
+            .severity
+            .as_deref()
+            .and_then(|s| s.parse::<Severity>().ok());
+        let requested = other.severity.as_deref().map(str::parse::<Severity>);
+        match (current, requested) {
+            (Some(current), Some(Ok(requested))) if requested > current => {}
+            (Some(current), Some(Ok(requested))) if requested == current => other.severity = None,
+            (_, Some(_)) => {
+                dropped.push("severity");
+                other.severity = None;
+            }
+            (_, None) => {}
+        }
+        dropped
+    }
+
+    fn compile(&self) -> Result<RegexDetector, String> {
+        let (Some(pattern), Some(finding_type), Some(severity)) =
+            (&self.pattern, &self.finding_type, &self.severity)
+        else {
+            return Err(format!(
+                "Detector '{}' needs `pattern`, `finding_type` and `severity`",
+                self.name
+            ));
+        };
+
+        let severity = severity
+            .parse::<Severity>()
+            .map_err(|err| format!("Detector '{}' has an {}", self.name, err))?;
+        let mut detector = RegexDetector::new(&self.name, pattern, finding_type, severity)
+            .map_err(|err| format!("Invalid detector pattern: {}", err))?;
+        detector.metadata = DetectorMetadata {
+            id: self.id.clone(),
+            tags: self.tags.clone().unwrap_or_default(),
+            description: self.description.clone(),
+            remediation: self.remediation.clone(),
+            references: self.references.clone().unwrap_or_default(),
+        };
+        detector.examples = self.examples.clone().unwrap_or_default();
+        detector.paths = compile_path_patterns(&self.name, self.paths.as_deref().unwrap_or(&[]))?;
+        detector.exclude_paths =
+            compile_path_patterns(&self.name, self.exclude_paths.as_deref().unwrap_or(&[]))?;
+        Ok(detector)
+    }
+}
+
+/// Where an active detector was defined and which layers changed it afterwards.
+#[derive(Debug, Clone)]
+pub struct DetectorOrigin {
+    pub defined_in: String,
+    pub overridden_in: Vec<String>,
+}
+
+pub struct ActiveDetector {
+    pub detector: RegexDetector,
+    pub origin: DetectorOrigin,
+}
+
+#[derive(Debug, Clone)]
+pub struct DisabledDetector {
+    pub name: String,
+    pub disabled_in: String,
+}
+
+/// Which detectors a scan runs, by tag and by rule ID or name. Empty lists select everything.
+/// Named profiles in `[profiles.<name>]` use the same keys.
+#[derive(Deserialize, Clone, Default, Debug, PartialEq, Eq)]
+pub struct DetectorSelection {
+    #[serde(default)]
+    pub only_tags: Vec<String>,
+    #[serde(default)]
+    pub skip_tags: Vec<String>,
+    #[serde(default)]
+    pub only_rules: Vec<String>,
+    #[serde(default)]
+    pub skip_rules: Vec<String>,
+}
+
+impl DetectorSelection {
+    /// with_overrides applies command-line filters on top of a profile: `only_*` lists
+    /// replace the profile's, `skip_*` lists add to them.
+    pub fn with_overrides(mut self, overrides: &DetectorSelection) -> DetectorSelection {
+        if !overrides.only_tags.is_empty() {
+            self.only_tags = overrides.only_tags.clone();
+        }
+        if !overrides.only_rules.is_empty() {
+            self.only_rules = overrides.only_rules.clone();
+        }
+        self.skip_tags.extend(overrides.skip_tags.iter().cloned());
+        self.skip_rules.extend(overrides.skip_rules.iter().cloned());
+        self
+    }
+
+    pub fn selects(&self, detector: &RegexDetector) -> bool {
+        let has_tag = |tags: &[String]| {
+            detector
+                .metadata
+                .tags
+                .iter()
+                .any(|tag| tags.iter().any(|wanted| wanted.eq_ignore_ascii_case(tag)))
+        };
+        let is_rule = |rules: &[String]| rules.iter().any(|rule| names_detector(rule, detector));
+
+        (self.only_tags.is_empty() || has_tag(&self.only_tags))
+            && (self.only_rules.is_empty() || is_rule(&self.only_rules))
+            && !has_tag(&self.skip_tags)
+            && !is_rule(&self.skip_rules)
+    }
+}
+
+/// names_detector matches a rule reference against a detector's ID (case-insensitive) or name.
+pub fn names_detector(rule: &str, detector: &RegexDetector) -> bool {
+    detector.name == rule
+        || detector
+            .metadata
+            .id
+            .as_deref()
+            .is_some_and(|id| id.eq_ignore_ascii_case(rule))
+}
+
+/// The merged result of every configuration layer.
+pub struct DetectorSet {
+    pub active: Vec<ActiveDetector>,
+    pub disabled: Vec<DisabledDetector>,
+    pub profiles: BTreeMap<String, DetectorSelection>,
+    /// Changes from untrusted layers that were ignored.
+    pub warnings: Vec<String>,
+}
+
+impl DetectorSet {
+    pub fn into_detectors(self) -> Vec<RegexDetector> {
+        self.active
+            .into_iter()
+            .map(|active| active.detector)
+            .collect()
+    }
+
+    /// select keeps the detectors chosen by `profile` and the command-line `filters`.
+    /// Unknown profiles, tags and rules are errors, so a typo cannot silently skip detectors.
+    pub fn select(
+        self,
+        profile: Option<&str>,
+        filters: &DetectorSelection,
+    ) -> Result<Vec<RegexDetector>, String> {
+        let base = match profile {
+            Some(name) => self.profiles.get(name).cloned().ok_or_else(|| {
+                format!(
+                    "Unknown profile '{}' (available: {})",
+                    name,
+                    self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                )
+            })?,
+            None => DetectorSelection::default(),
+        };
+        let selection = base.with_overrides(filters);
+        let detectors = self.into_detectors();
+
+        for tag in selection.only_tags.iter().chain(&selection.skip_tags) {
+            let known = detectors.iter().any(|detector| {
+                detector
+                    .metadata
+                    .tags
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(tag))
+            });
+            if !known {
+                return Err(format!("No active detector has the tag '{tag}'"));
+            }
+        }
+        for rule in selection.only_rules.iter().chain(&selection.skip_rules) {
+            if !detectors
+                .iter()
+                .any(|detector| names_detector(rule, detector))
+            {
+                return Err(format!("No active detector with id or name '{rule}'"));
+            }
+        }
+
+        Ok(detectors
+            .into_iter()
+            .filter(|detector| selection.selects(detector))
+            .collect())
+    }
+}
+
+/// discover_sources lists the configuration files present on this machine, lowest precedence first.
+/// The project layer is repository content, so it is only trusted once the user says so.
+pub fn discover_sources() -> Vec<ConfigSource> {
+    let candidates = [
+        (
+            ConfigLayer::Install,
+            std::env::current_exe()
+                .ok()
+                .and_then(|p| p.parent().map(|d| d.join("detectors.toml"))),
+        ),
+        (
+            ConfigLayer::User,
+            dirs::config_dir().map(|p| p.join("keywatch").join("detectors.toml")),
+        ),
+        (ConfigLayer::Project, Some(PathBuf::from("detectors.toml"))),
+        (
+            ConfigLayer::Env,
+            std::env::var_os("KEYWATCH_CONFIG_PATH").map(PathBuf::from),
+        ),
+    ];
+
+    candidates
+        .into_iter()
+        .filter_map(|(layer, path)| {
+            path.filter(|p| p.exists()).map(|path| ConfigSource {
+                trusted: layer != ConfigLayer::Project || trust::is_trusted(&path),
+                layer,
+                path,
+            })
+        })
+        .collect()
+}
+
+/// resolve_detectors merges the built-in detectors with each source in order.
+pub fn resolve_detectors(sources: &[ConfigSource]) -> Result<DetectorSet, String> {
+    let mut resolver = Resolver::default();
+    resolver.apply_toml(
+        ConfigLayer::BuiltIn.as_str(),
+        DEFAULT_DETECTORS,
+        None,
+        &ConfigSource {
+            layer: ConfigLayer::BuiltIn,
+            path: PathBuf::new(),
+            trusted: true,
+        },
+    )?;
+    for source in sources {
+        resolver.apply_source(source)?;
+    }
+    resolver.finish()
+}
+
+/// resolve_file loads a single configuration file (and whatever it extends) on its own,
+/// without the built-in detectors underneath.
+pub fn resolve_file(path: &Path) -> Result<DetectorSet, String> {
+    let mut resolver = Resolver::default();
+    resolver.apply_source(&ConfigSource {
+        layer: ConfigLayer::File,
+        path: path.to_path_buf(),
+        trusted: true,
+    })?;
+    resolver.finish()
+}
+
+#[derive(Default)]
+struct Resolver {
+    entries: Vec<(DetectorEntry, DetectorOrigin)>,
+    disabled: Vec<DisabledDetector>,
+    profiles: BTreeMap<String, DetectorSelection>,
+    warnings: Vec<String>,
+    visiting: Vec<PathBuf>,
+}
+
+impl Resolver {
+    fn apply_source(&mut self, source: &ConfigSource) -> Result<(), String> {
+        let canonical = fs::canonicalize(&source.path).unwrap_or_else(|_| source.path.clone());
+        if self.visiting.contains(&canonical) {
+            return Err(format!(
+                "Circular extends: {} includes itself",
+                source.path.display()
+            ));
+        }
+
+        let contents = fs::read_to_string(&source.path)
+            .map_err(|err| format!("Failed to read {}: {}", source.path.display(), err))?;
+
+        self.visiting.push(canonical);
+        let result = self.apply_toml(&source.label(), &contents, source.path.parent(), source);
+        self.visiting.pop();
+        result
+    }
+
+    fn apply_toml(
+        &mut self,
+        label: &str,
+        contents: &str,
+        base_dir: Option<&Path>,
+        source: &ConfigSource,
+    ) -> Result<(), String> {
+        let file: ConfigFile = toml::from_str(contents)
+            .map_err(|err| format!("Failed to parse {}: {}", label, err))?;
+
+        for extended in &file.extends {
+            let path = base_dir.map_or_else(|| PathBuf::from(extended), |dir| dir.join(extended));
+            self.apply_source(&ConfigSource {
+                path,
+                ..source.clone()
+            })?;
+        }
+
+        if !source.trusted && !file.disable.is_empty() {
+            self.warnings.push(format!(
+                "{label}: ignored `disable = {:?}`; untrusted config may not disable detectors",
+                file.disable
+            ));
+        } else {
+            self.disable(&file.disable, label)?;
+        }
+
+        for entry in file.detectors {
+            self.merge(entry, label, source.trusted)?;
+        }
+
+        for (name, profile) in file.profiles {
+            match self.profiles.get(&name) {
+                Some(existing) if !source.trusted && *existing != profile => {
+                    self.warnings.push(format!(
+                        "{label}: ignored redefinition of profile '{name}'; untrusted config may only add profiles"
+                    ));
+                }
+                _ => {
+                    self.profiles.insert(name, profile);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn disable(&mut self, names: &[String], label: &str) -> Result<(), String> {
+        for name in names {
+            let index = self
+                .entries
+                .iter()
+                .position(|(entry, _)| &entry.name == name)
+                .ok_or_else(|| format!("{label} disables unknown detector '{name}'"))?;
+            self.entries.remove(index);
+            self.disabled.retain(|disabled| &disabled.name != name);
+            self.disabled.push(DisabledDetector {
+                name: name.clone(),
+                disabled_in: label.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn merge(
+        &mut self,
+        mut entry: DetectorEntry,
+        label: &str,
+        trusted: bool,
+    ) -> Result<(), String> {
+        if let Some((existing, origin)) = self
+            .entries
+            .iter_mut()
+            .find(|(existing, _)| existing.name == entry.name)
+        {
+            if !trusted {
+                let dropped = existing.restrict_override(&mut entry);
+                if !dropped.is_empty() {
+                    self.warnings.push(format!(
+                        "{label}: ignored changes to {} of '{}'; untrusted config may only raise severities",
+                        dropped.join(", "),
+                        entry.name
+                    ));
+                }
+                if entry.severity.is_none() {
+                    return Ok(());
+                }
+            }
+            existing.apply_override(entry);
+            if !origin.overridden_in.iter().any(|layer| layer == label) {
+                origin.overridden_in.push(label.to_string());
+            }
+            return Ok(());
+        }
+
+        if entry.pattern.is_none() || entry.finding_type.is_none() || entry.severity.is_none() {
+            return Err(format!(
+                "Detector '{}' in {} does not override an existing detector, so it needs `pattern`, `finding_type` and `severity`",
+                entry.name, label
+            ));
+        }
+
+        self.disabled.retain(|disabled| disabled.name != entry.name);
+        self.entries.push((
+            entry,
+            DetectorOrigin {
+                defined_in: label.to_string(),
+                overridden_in: Vec::new(),
+            },
+        ));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<DetectorSet, String> {
+        let active = self
+            .entries
+            .into_iter()
+            .map(|(entry, origin)| {
+                Ok(ActiveDetector {
+                    detector: entry.compile()?,
+                    origin,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(DetectorSet {
+            active,
+            disabled: self.disabled,
+            profiles: self.profiles,
+            warnings: self.warnings,
+        })
+    }
+}
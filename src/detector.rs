@@ -1,81 +1,412 @@
+use crate::config::{self, DetectorSelection};
+use crate::error::{self, Error};
+use crate::report::{DetectorMetadata, Severity};
+use crate::utils::matches_path_patterns;
+use glob::Pattern;
 use regex::Regex;
 use serde::Deserialize;
-use std::fs;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
 
-/// Represents a secret detector used in scanning.
-pub struct Detector {
+/// The file a detector is asked to scan.
+pub struct ScanContext<'a> {
+    pub path: &'a str,
+    pub content: &'a str,
+}
+
+/// A secret found by a detector. Line numbers start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub line_number: usize,
+    pub matched_content: String,
+}
+
+static NO_METADATA: DetectorMetadata = DetectorMetadata {
+    id: None,
+    tags: Vec::new(),
+    description: None,
+    remediation: None,
+    references: Vec::new(),
+};
+
+/// A detector the scanner can run. Regex rules from detectors.toml are one implementation;
+/// library users can implement it for detectors that need real logic, such as parsing a
+/// manifest, and add them to a DetectorRegistry.
+pub trait Detector: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn finding_type(&self) -> &str;
+
+    fn severity(&self) -> Severity;
+
+    fn metadata(&self) -> &DetectorMetadata {
+        &NO_METADATA
+    }
+
+    /// applies_to decides whether the detector runs on `path` at all.
+    fn applies_to(&self, _path: &str, _scan_roots: &[Option<String>]) -> bool {
+        true
+    }
+
+    fn scan(&self, ctx: &ScanContext) -> Vec<Match>;
+
+    /// fingerprint describes everything that decides this detector's matches, so the scan
+    /// cache can tell when stored results are stale. Scans with a detector that has no
+    /// fingerprint, the default, are not cached.
+    fn fingerprint(&self) -> Option<String> {
+        None
+    }
+
+    /// find_all returns every match, where `scan` may report only the first per line.
+    /// Redaction uses it so that no occurrence is left behind.
+    fn find_all(&self, ctx: &ScanContext) -> Vec<Match> {
+        self.scan(ctx)
+    }
+
+    /// spans_lines tells streaming scans, which read one line at a time, that the
+    /// detector's matches can cover several lines, so it must see the lines around them.
+    fn spans_lines(&self) -> bool {
+        false
+    }
+
+    /// secret_range is the byte range of the secret itself within `matched_content`.
+    /// Matches may include context such as the key of a `password = '...'` assignment,
+    /// which quick fixes must leave in place. The default is the whole match.
+    fn secret_range(&self, matched_content: &str) -> Range<usize> {
+        0..matched_content.len()
+    }
+}
+
+/// Finds where the matches detectors reported sit in the content they scanned. Matches
+/// only carry their text and line, so repeated matches of the same text by one detector
+/// on one line are located in turn.
+pub(crate) struct MatchLocator<'a> {
+    content: &'a str,
+    line_starts: Vec<usize>,
+    /// Where to continue searching for a detector's next match of the same text on a line.
+    resume: BTreeMap<(&'a str, usize, &'a str), usize>,
+}
+
+impl<'a> MatchLocator<'a> {
+    pub(crate) fn new(content: &'a str) -> Self {
+        Self {
+            content,
+            line_starts: std::iter::once(0)
+                .chain(content.match_indices('\n').map(|(index, _)| index + 1))
+                .collect(),
+            resume: BTreeMap::new(),
+        }
+    }
+
+    /// locate returns the byte offset of `found`, reported by the detector named
+    /// `detector`, or None when it is not where the detector said.
+    pub(crate) fn locate(&mut self, detector: &'a str, found: &'a Match) -> Option<usize> {
+        let line_start = found
+            .line_number
+            .checked_sub(1)
+            .and_then(|index| self.line_starts.get(index))?;
+        let key = (detector, found.line_number, found.matched_content.as_str());
+        let from = self.resume.get(&key).copied().unwrap_or(*line_start);
+        let offset = from + self.content[from..].find(&found.matched_content)?;
+        self.resume
+            .insert(key, offset + found.matched_content.len());
+        Some(offset)
+    }
+}
+
+/// The detectors a scan runs, in registration order.
+#[derive(Default)]
+pub struct DetectorRegistry {
+    detectors: Vec<Box<dyn Detector>>,
+}
+
+impl DetectorRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, detector: impl Detector + 'static) -> &mut Self {
+        self.detectors.push(Box::new(detector));
+        self
+    }
+
+    /// append moves every detector from `other` to the end of this registry.
+    pub fn append(&mut self, mut other: DetectorRegistry) -> &mut Self {
+        self.detectors.append(&mut other.detectors);
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Detector> {
+        self.detectors.iter().map(Box::as_ref)
+    }
+
+    pub fn len(&self) -> usize {
+        self.detectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.detectors.is_empty()
+    }
+}
+
+impl From<Vec<RegexDetector>> for DetectorRegistry {
+    fn from(detectors: Vec<RegexDetector>) -> Self {
+        let mut registry = DetectorRegistry::new();
+        for detector in detectors {
+            registry.register(detector);
+        }
+        registry
+    }
+}
+
+/// A detector defined by a regex in detectors.toml.
+pub struct RegexDetector {
     pub name: String,
     pub regex: Regex,
     pub finding_type: String,
-    pub severity: String,
+    pub severity: Severity,
+    pub metadata: DetectorMetadata,
+    pub examples: DetectorExamples,
+    /// Globs a file must match for the detector to run; empty means every file.
+    pub paths: Vec<Pattern>,
+    /// Globs of files the detector never runs on.
+    pub exclude_paths: Vec<Pattern>,
+}
+
+/// Sample inputs a detector must (positive) and must not (negative) match.
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct DetectorExamples {
+    #[serde(default)]
+    pub positive: Vec<String>,
+    #[serde(default)]
+    pub negative: Vec<String>,
 }
 
-impl Detector {
+impl RegexDetector {
     pub fn new(
         name: &str,
         pattern: &str,
         finding_type: &str,
-        severity: &str,
-    ) -> Result<Detector, regex::Error> {
-        Ok(Detector {
+        severity: Severity,
+    ) -> Result<RegexDetector, regex::Error> {
+        Ok(RegexDetector {
             name: name.to_string(),
             regex: Regex::new(pattern)?,
             finding_type: finding_type.to_string(),
-            severity: severity.to_string(),
+            severity,
+            metadata: DetectorMetadata::default(),
+            examples: DetectorExamples::default(),
+            paths: Vec::new(),
+            exclude_paths: Vec::new(),
         })
     }
+
+    /// Multiline detectors run against the whole file instead of line by line.
+    pub fn is_multiline(&self) -> bool {
+        self.regex.as_str().contains("(?s)")
+    }
+
+    /// matches_text applies the detector to `text` the same way the scanner would:
+    /// multiline detectors see the whole text, line detectors see one line at a time.
+    pub fn matches_text(&self, text: &str) -> bool {
+        if self.is_multiline() {
+            self.regex.is_match(text)
+        } else {
+            text.lines().any(|line| self.regex.is_match(line))
+        }
+    }
 }
 
-/// This structure mirrors the detectors.toml file layout.
-#[derive(Deserialize)]
-struct DetectorsConfig {
-    detectors: Vec<DetectorConfig>,
+impl Detector for RegexDetector {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn finding_type(&self) -> &str {
+        &self.finding_type
+    }
+
+    fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    fn metadata(&self) -> &DetectorMetadata {
+        &self.metadata
+    }
+
+    /// applies_to checks the detector's path scoping, evaluated like `scan --exclude`:
+    /// against the full path, the file name, or the path relative to a scan root.
+    fn applies_to(&self, path: &str, scan_roots: &[Option<String>]) -> bool {
+        (self.paths.is_empty() || matches_path_patterns(path, scan_roots, &self.paths))
+            && !matches_path_patterns(path, scan_roots, &self.exclude_paths)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        let globs = |patterns: &[Pattern]| {
+            patterns
+                .iter()
+                .map(Pattern::as_str)
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        Some(format!(
+            "{}\0{}\0{}\0{}",
+            self.name,
+            self.regex.as_str(),
+            globs(&self.paths),
+            globs(&self.exclude_paths)
+        ))
+    }
+
+    /// Multiline detectors report their first match in the file; line detectors report
+    /// the first match on each line.
+    fn scan(&self, ctx: &ScanContext) -> Vec<Match> {
+        if self.is_multiline() {
+            return self
+                .regex
+                .find(ctx.content)
+                .map(|mat| Match {
+                    line_number: ctx.content[..mat.start()].matches('\n').count() + 1,
+                    matched_content: mat.as_str().to_string(),
+                })
+                .into_iter()
+                .collect();
+        }
+
+        ctx.content
+            .lines()
+            .enumerate()
+            .filter_map(|(line_idx, line)| {
+                self.regex.find(line).map(|mat| Match {
+                    line_number: line_idx + 1,
+                    matched_content: mat.as_str().to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn find_all(&self, ctx: &ScanContext) -> Vec<Match> {
+        if self.is_multiline() {
+            return self
+                .regex
+                .find_iter(ctx.content)
+                .map(|mat| Match {
+                    line_number: ctx.content[..mat.start()].matches('\n').count() + 1,
+                    matched_content: mat.as_str().to_string(),
+                })
+                .collect();
+        }
+
+        ctx.content
+            .lines()
+            .enumerate()
+            .flat_map(|(line_idx, line)| {
+                self.regex.find_iter(line).map(move |mat| Match {
+                    line_number: line_idx + 1,
+                    matched_content: mat.as_str().to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn spans_lines(&self) -> bool {
+        self.is_multiline()
+    }
+
+    /// A pattern with a group named `secret` marks which part of a match is the secret.
+    fn secret_range(&self, matched_content: &str) -> Range<usize> {
+        self.regex
+            .captures(matched_content)
+            .and_then(|captures| captures.name("secret"))
+            .map_or(0..matched_content.len(), |secret| secret.range())
+    }
 }
 
-#[derive(Deserialize)]
-struct DetectorConfig {
-    name: String,
-    pattern: String,
-    finding_type: String,
-    severity: String,
+/// Keys understood on a `[[detectors]]` entry; anything else is ignored when loading.
+pub(crate) const DETECTOR_KEYS: &[&str] = &[
+    "name",
+    "id",
+    "pattern",
+    "finding_type",
+    "severity",
+    "tags",
+    "description",
+    "remediation",
+    "references",
+    "examples",
+    "paths",
+    "exclude_paths",
+];
+
+/// Top-level keys understood in a detectors.toml file.
+pub(crate) const CONFIG_KEYS: &[&str] = &["extends", "disable", "detectors", "profiles"];
+
+/// Keys understood in a `[profiles.<name>]` table.
+pub(crate) const PROFILE_KEYS: &[&str] = &["only_tags", "skip_tags", "only_rules", "skip_rules"];
+
+/// The shipped detectors.toml, compiled into the binary as the built-in default set.
+pub(crate) const DEFAULT_DETECTORS: &str = include_str!("../detectors.toml");
+
+/// The detectors chosen from the configuration layers, with any warnings raised while
+/// merging them.
+pub struct ConfiguredDetectors {
+    pub detectors: Vec<RegexDetector>,
+    /// Changes an untrusted project config was not allowed to make. Callers decide how to
+    /// show them; the CLI prints them on stderr.
+    pub warnings: Vec<String>,
 }
 
-fn find_detectors_config() -> std::path::PathBuf {
-    std::env::var("KEYWATCH_CONFIG_PATH")
-        .map(std::path::PathBuf::from)
-        .ok()
-        .filter(|p| p.exists())
-        .or_else(|| {
-            let p = std::path::PathBuf::from("detectors.toml");
-            if p.exists() { Some(p) } else { None }
-        })
-        .or_else(|| {
-            dirs::config_dir()
-                .map(|p| p.join("keywatch").join("detectors.toml"))
-                .filter(|p| p.exists())
-        })
-        .or_else(|| {
-            std::env::current_exe()
-                .ok()
-                .and_then(|p| p.parent().map(|d| d.join("detectors.toml")))
-                .filter(|p| p.exists())
-        })
-        .unwrap_or_else(|| std::path::PathBuf::from("detectors.toml"))
+/// initialize_detectors returns the built-in detectors merged with every configuration layer
+/// found on this machine: install dir, user config dir, project and `KEYWATCH_CONFIG_PATH`.
+/// Only the detectors chosen by `profile` and `filters` are returned.
+pub fn initialize_detectors(
+    profile: Option<&str>,
+    filters: &DetectorSelection,
+) -> error::Result<ConfiguredDetectors> {
+    let mut detector_set =
+        config::resolve_detectors(&config::discover_sources()).map_err(Error::Config)?;
+    let warnings = std::mem::take(&mut detector_set.warnings)
+        .into_iter()
+        .map(|warning| format!("{warning} (run `key-watch config trust` to allow it)"))
+        .collect();
+    let detectors = detector_set
+        .select(profile, filters)
+        .map_err(Error::Selection)?;
+    Ok(ConfiguredDetectors {
+        detectors,
+        warnings,
+    })
 }
 
-/// initialize_detectors reads the detector definitions from detectors.toml and returns a vector of Detector.
-pub fn initialize_detectors() -> Result<Vec<Detector>, Box<dyn std::error::Error>> {
-    let config_path = find_detectors_config();
-    let toml_contents = fs::read_to_string(&config_path)
-        .map_err(|err| format!("Failed to read {}: {}", config_path.display(), err))?;
+/// default_detectors compiles the built-in detector set.
+pub fn default_detectors() -> error::Result<Vec<RegexDetector>> {
+    Ok(config::resolve_detectors(&[])
+        .map_err(Error::Config)?
+        .into_detectors())
+}
 
-    let config: DetectorsConfig = toml::from_str(&toml_contents)
-        .map_err(|err| format!("Failed to parse detectors.toml: {}", err))?;
+/// load_detectors reads the detector definitions from a specific detectors.toml file,
+/// following its `extends` but without the built-in detectors underneath.
+pub fn load_detectors(config_path: &Path) -> error::Result<Vec<RegexDetector>> {
+    Ok(config::resolve_file(config_path)
+        .map_err(Error::Config)?
+        .into_detectors())
+}
 
-    Ok(config
-        .detectors
-        .into_iter()
-        .map(|det| Detector::new(&det.name, &det.pattern, &det.finding_type, &det.severity))
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|err| format!("Invalid detector pattern: {}", err))?)
+pub(crate) fn compile_path_patterns(
+    detector_name: &str,
+    patterns: &[String],
+) -> Result<Vec<Pattern>, String> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|err| {
+                format!(
+                    "Detector '{}' has an invalid path pattern '{}': {}",
+                    detector_name, pattern, err
+                )
+            })
+        })
+        .collect()
 }
@@ -0,0 +1,228 @@
+use crate::report::SkipReason;
+use encoding_rs::WINDOWS_1252;
+use std::borrow::Cow;
+use std::char::REPLACEMENT_CHARACTER;
+use std::fmt;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+const UTF32LE_BOM: &[u8] = &[0xFF, 0xFE, 0x00, 0x00];
+const UTF32BE_BOM: &[u8] = &[0x00, 0x00, 0xFE, 0xFF];
+
+/// How many leading bytes are inspected to recognize UTF-16 without a BOM.
+const SNIFF_LEN: usize = 4096;
+
+/// A text encoding files are decoded from before detectors see them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+    Windows1252,
+    /// Mostly UTF-8 with some invalid bytes, which decode to U+FFFD.
+    LossyUtf8,
+}
+
+impl TextEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Utf8 => "UTF-8",
+            Self::Utf16Le => "UTF-16LE",
+            Self::Utf16Be => "UTF-16BE",
+            Self::Utf32Le => "UTF-32LE",
+            Self::Utf32Be => "UTF-32BE",
+            Self::Windows1252 => "windows-1252",
+            Self::LossyUtf8 => "UTF-8 (lossy)",
+        }
+    }
+
+    fn bom(&self) -> &'static [u8] {
+        match self {
+            Self::Utf8 => UTF8_BOM,
+            Self::Utf16Le => UTF16LE_BOM,
+            Self::Utf16Be => UTF16BE_BOM,
+            Self::Utf32Le => UTF32LE_BOM,
+            Self::Utf32Be => UTF32BE_BOM,
+            Self::Windows1252 | Self::LossyUtf8 => &[],
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// File content decoded to text. Decoding maps each character on its own, so line breaks
+/// and therefore line numbers are the same as in the original file.
+#[derive(Debug)]
+pub struct DecodedText<'a> {
+    pub text: Cow<'a, str>,
+    pub encoding: TextEncoding,
+    /// Whether the original started with a byte order mark, which is not part of `text`.
+    pub bom: bool,
+}
+
+impl DecodedText<'_> {
+    /// encode converts `text`, typically an edited copy of this text, back to the original
+    /// encoding and byte order mark. Returns None when that would lose data: lossy UTF-8
+    /// cannot restore its invalid bytes, and windows-1252 cannot represent every character.
+    pub fn encode(&self, text: &str) -> Option<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(text.len() + 4);
+        if self.bom {
+            bytes.extend_from_slice(self.encoding.bom());
+        }
+        match self.encoding {
+            TextEncoding::Utf8 => bytes.extend_from_slice(text.as_bytes()),
+            TextEncoding::Utf16Le => text
+                .encode_utf16()
+                .for_each(|unit| bytes.extend_from_slice(&unit.to_le_bytes())),
+            TextEncoding::Utf16Be => text
+                .encode_utf16()
+                .for_each(|unit| bytes.extend_from_slice(&unit.to_be_bytes())),
+            TextEncoding::Utf32Le => text
+                .chars()
+                .for_each(|ch| bytes.extend_from_slice(&(ch as u32).to_le_bytes())),
+            TextEncoding::Utf32Be => text
+                .chars()
+                .for_each(|ch| bytes.extend_from_slice(&(ch as u32).to_be_bytes())),
+            TextEncoding::Windows1252 => {
+                let (encoded, _, unmappable) = WINDOWS_1252.encode(text);
+                if unmappable {
+                    return None;
+                }
+                bytes.extend_from_slice(&encoded);
+            }
+            TextEncoding::LossyUtf8 => return None,
+        }
+        Some(bytes)
+    }
+}
+
+/// decode detects the encoding of file content and decodes it:
+///
+/// 1. A byte order mark selects UTF-8, UTF-16 or UTF-32.
+/// 2. Without one, content whose every other byte is NUL is read as UTF-16.
+/// 3. Any other content containing NUL bytes is binary and skipped.
+/// 4. Valid UTF-8 is used as is. Otherwise content that is mostly UTF-8 is decoded lossily,
+///    and anything else as windows-1252, the superset of Latin-1 legacy files use.
+pub fn decode(bytes: &[u8]) -> Result<DecodedText<'_>, SkipReason> {
+    if let Some(mut encoding) = sniff_bom(bytes) {
+        let body = &bytes[encoding.bom().len()..];
+        if encoding == TextEncoding::Utf8 && std::str::from_utf8(body).is_err() {
+            encoding = TextEncoding::LossyUtf8;
+        }
+        return Ok(DecodedText {
+            text: decode_as(encoding, body),
+            encoding,
+            bom: true,
+        });
+    }
+    if let Some(encoding) = sniff_utf16(bytes) {
+        return Ok(DecodedText {
+            text: decode_as(encoding, bytes),
+            encoding,
+            bom: false,
+        });
+    }
+    if bytes.contains(&0) {
+        return Err(SkipReason::Binary);
+    }
+
+    let encoding = match std::str::from_utf8(bytes) {
+        Ok(_) => TextEncoding::Utf8,
+        Err(_) if mostly_utf8(bytes) => TextEncoding::LossyUtf8,
+        Err(_) => TextEncoding::Windows1252,
+    };
+    Ok(DecodedText {
+        text: decode_as(encoding, bytes),
+        encoding,
+        bom: false,
+    })
+}
+
+fn sniff_bom(bytes: &[u8]) -> Option<TextEncoding> {
+    // UTF-32LE's BOM starts with UTF-16LE's, so it is checked first.
+    [
+        TextEncoding::Utf32Le,
+        TextEncoding::Utf32Be,
+        TextEncoding::Utf8,
+        TextEncoding::Utf16Le,
+        TextEncoding::Utf16Be,
+    ]
+    .into_iter()
+    .find(|encoding| bytes.starts_with(encoding.bom()))
+}
+
+/// sniff_utf16 recognizes UTF-16 without a BOM from mostly-ASCII text, where the high
+/// byte of nearly every code unit is zero and the low byte almost never is.
+fn sniff_utf16(bytes: &[u8]) -> Option<TextEncoding> {
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    let units = sample.len() / 2;
+    if units < 2 || bytes.len() % 2 != 0 {
+        return None;
+    }
+    let zeros_at = |offset: usize| {
+        sample
+            .chunks_exact(2)
+            .filter(|pair| pair[offset] == 0)
+            .count()
+    };
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    let mostly = |count: usize| count * 10 >= units * 9;
+    let rarely = |count: usize| count * 20 <= units;
+    if mostly(odd) && rarely(even) {
+        Some(TextEncoding::Utf16Le)
+    } else if mostly(even) && rarely(odd) {
+        Some(TextEncoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+/// mostly_utf8 reports whether invalid UTF-8 looks like damage to UTF-8 text rather than
+/// a single-byte encoding: valid multi-byte characters outnumber invalid bytes.
+fn mostly_utf8(bytes: &[u8]) -> bool {
+    let (mut multibyte, mut invalid) = (0, 0);
+    for chunk in bytes.utf8_chunks() {
+        multibyte += chunk.valid().chars().filter(|ch| !ch.is_ascii()).count();
+        invalid += chunk.invalid().len();
+    }
+    multibyte > invalid
+}
+
+fn decode_as(encoding: TextEncoding, bytes: &[u8]) -> Cow<'_, str> {
+    match encoding {
+        TextEncoding::Utf8 | TextEncoding::LossyUtf8 => String::from_utf8_lossy(bytes),
+        TextEncoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+        TextEncoding::Utf16Be => decode_utf16(bytes, u16::from_be_bytes),
+        TextEncoding::Utf32Le => decode_utf32(bytes, u32::from_le_bytes),
+        TextEncoding::Utf32Be => decode_utf32(bytes, u32::from_be_bytes),
+        TextEncoding::Windows1252 => WINDOWS_1252.decode_without_bom_handling(bytes).0,
+    }
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Cow<'_, str> {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    Cow::Owned(
+        char::decode_utf16(units)
+            .map(|ch| ch.unwrap_or(REPLACEMENT_CHARACTER))
+            .collect(),
+    )
+}
+
+fn decode_utf32(bytes: &[u8], unit: fn([u8; 4]) -> u32) -> Cow<'_, str> {
+    Cow::Owned(
+        bytes
+            .chunks_exact(4)
+            .map(|quad| {
+                char::from_u32(unit([quad[0], quad[1], quad[2], quad[3]]))
+                    .unwrap_or(REPLACEMENT_CHARACTER)
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,62 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors returned by KeyWatch's library API.
+#[derive(Debug)]
+pub enum Error {
+    /// A detector configuration could not be read, merged or compiled.
+    Config(String),
+    /// A profile, tag or rule filter did not match any active detector.
+    Selection(String),
+    /// An exclude glob passed to the scanner is not a valid pattern.
+    InvalidExclude {
+        pattern: String,
+        source: glob::PatternError,
+    },
+    /// Reading a file or stream failed.
+    Io { path: PathBuf, source: io::Error },
+    /// A command to run could not be started.
+    Spawn { command: String, source: io::Error },
+    /// A server could not listen on the requested address.
+    Listen { address: String, source: io::Error },
+    /// A container image is not a `docker save` tarball or OCI layout KeyWatch can read.
+    Image { path: PathBuf, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(message) | Error::Selection(message) => f.write_str(message),
+            Error::InvalidExclude { pattern, source } => {
+                write!(f, "Invalid exclude pattern '{}': {}", pattern, source)
+            }
+            Error::Io { path, source } => {
+                write!(f, "Failed to read {}: {}", path.display(), source)
+            }
+            Error::Spawn { command, source } => {
+                write!(f, "Failed to run '{}': {}", command, source)
+            }
+            Error::Listen { address, source } => {
+                write!(f, "Failed to listen on {}: {}", address, source)
+            }
+            Error::Image { path, message } => {
+                write!(f, "Failed to read image {}: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Config(_) | Error::Selection(_) | Error::Image { .. } => None,
+            Error::InvalidExclude { source, .. } => Some(source),
+            Error::Io { source, .. }
+            | Error::Spawn { source, .. }
+            | Error::Listen { source, .. } => Some(source),
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use crate::error::{self, Error};
+use crate::redact::{Replacement, write_redacted};
+use crate::scanner::Scanner;
+use std::io::{self, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a stream may be quiet before the incomplete line it ends with is written.
+const IDLE_FLUSH: Duration = Duration::from_millis(100);
+
+/// How long the last word of an incomplete line is held back after the stream goes quiet,
+/// in case it is the start of a secret whose rest has not been written yet.
+const WORD_HOLD: Duration = Duration::from_secs(1);
+
+/// Longest trailing word held back; anything before it is written after `IDLE_FLUSH`.
+const MAX_HELD_WORD: usize = 1024;
+
+/// How a wrapped command ended and how many secrets were masked in its output.
+#[derive(Debug)]
+pub struct ExecOutcome {
+    pub status: ExitStatus,
+    pub redactions: usize,
+}
+
+/// run_masked runs `command` with stdin inherited and its stdout and stderr piped through
+/// the scanner's detectors. Each line is redacted and written to the matching stream as
+/// soon as it is complete, so the two streams interleave line by line; an incomplete
+/// line, such as a prompt, is written once the stream goes quiet. SIGINT and SIGTERM sent
+/// to the wrapper are forwarded to the command while it runs.
+pub fn run_masked(
+    scanner: &Scanner,
+    command: &[String],
+    replacement: &Replacement,
+) -> error::Result<ExecOutcome> {
+    let (program, args) = command.split_first().ok_or_else(|| Error::Spawn {
+        command: String::new(),
+        source: io::Error::new(io::ErrorKind::InvalidInput, "no command given"),
+    })?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|source| Error::Spawn {
+            command: program.clone(),
+            source,
+        })?;
+    #[cfg(unix)]
+    let _forwarding = signals::forward_to(child.id());
+    let child_stdout = child.stdout.take().expect("stdout is piped");
+    let child_stderr = child.stderr.take().expect("stderr is piped");
+
+    let (stdout_redactions, stderr_redactions) = thread::scope(|scope| {
+        let stdout_thread = scope
+            .spawn(|| mask_stream(scanner, "<stdout>", child_stdout, io::stdout(), replacement));
+        let stderr_redactions =
+            mask_stream(scanner, "<stderr>", child_stderr, io::stderr(), replacement);
+        let stdout_redactions = stdout_thread
+            .join()
+            .expect("stdout redaction thread panicked");
+        (stdout_redactions, stderr_redactions)
+    });
+
+    let status = child.wait().map_err(|source| Error::Spawn {
+        command: program.clone(),
+        source,
+    })?;
+
+    Ok(ExecOutcome {
+        status,
+        redactions: stdout_redactions? + stderr_redactions?,
+    })
+}
+
+/// mask_stream copies `reader` to `writer`, redacting each line. Lines end at `\n` or at
+/// the `\r` progress output uses to redraw a line. When the stream has been quiet for
+/// `IDLE_FLUSH`, or the incomplete line has been waiting that long, it is written except
+/// for its last word, which waits until the stream has been quiet for `WORD_HOLD`, so a
+/// secret written in two parts is still redacted whole.
+fn mask_stream(
+    scanner: &Scanner,
+    name: &str,
+    reader: impl Read + Send + 'static,
+    mut writer: impl Write,
+    replacement: &Replacement,
+) -> error::Result<usize> {
+    let io_error = |source| Error::Io {
+        path: name.into(),
+        source,
+    };
+    let chunks = read_chunks(reader);
+    let mut pending = Vec::new();
+    let mut last_read = Instant::now();
+    let mut pending_since = Instant::now();
+    let mut redactions = 0;
+    let mut write = |bytes: &[u8], redactions: &mut usize| -> error::Result<()> {
+        for line in bytes.split_inclusive(|&byte| byte == b'\n' || byte == b'\r') {
+            *redactions +=
+                write_redacted(scanner, name, line, &mut writer, replacement).map_err(io_error)?;
+        }
+        Ok(())
+    };
+
+    loop {
+        match chunks.recv_timeout(IDLE_FLUSH) {
+            Ok(chunk) => {
+                let chunk = chunk.map_err(io_error)?;
+                if pending.is_empty() {
+                    pending_since = Instant::now();
+                }
+                pending.extend_from_slice(&chunk);
+                last_read = Instant::now();
+                if let Some(end) = pending
+                    .iter()
+                    .rposition(|&byte| byte == b'\n' || byte == b'\r')
+                {
+                    write(&pending[..=end], &mut redactions)?;
+                    pending.drain(..=end);
+                    pending_since = last_read;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                if !pending.is_empty() {
+                    write(&pending, &mut redactions)?;
+                }
+                return Ok(redactions);
+            }
+        }
+
+        // Output that keeps coming without a line break is written too, so waiting for
+        // the stream to go quiet does not hold it back forever.
+        let end = if last_read.elapsed() >= WORD_HOLD {
+            complete_utf8_len(&pending)
+        } else if pending_since.elapsed() >= IDLE_FLUSH {
+            trailing_word_start(&pending)
+        } else {
+            0
+        };
+        if end > 0 {
+            write(&pending[..end], &mut redactions)?;
+            pending.drain(..end);
+            pending_since = Instant::now();
+        }
+    }
+}
+
+/// read_chunks reads `reader` on its own thread and hands over whatever each read
+/// returns, so the caller can notice when the stream goes quiet. The channel closes at
+/// the end of the stream or after the first error.
+fn read_chunks(mut reader: impl Read + Send + 'static) -> mpsc::Receiver<io::Result<Vec<u8>>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = vec![0; 8192];
+        loop {
+            let chunk = match reader.read(&mut buffer) {
+                Ok(0) => return,
+                Ok(read) => Ok(buffer[..read].to_vec()),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => Err(err),
+            };
+            let failed = chunk.is_err();
+            if sender.send(chunk).is_err() || failed {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+/// trailing_word_start is where the last whitespace-separated word of `bytes` starts,
+/// keeping at most `MAX_HELD_WORD` bytes of it.
+fn trailing_word_start(bytes: &[u8]) -> usize {
+    let word_start = bytes
+        .iter()
+        .rposition(u8::is_ascii_whitespace)
+        .map_or(0, |space| space + 1);
+    let mut start = word_start.max(bytes.len().saturating_sub(MAX_HELD_WORD));
+    // Never split a UTF-8 character.
+    while bytes
+        .get(start)
+        .is_some_and(|&byte| byte & 0b1100_0000 == 0b1000_0000)
+    {
+        start += 1;
+    }
+    start
+}
+
+/// complete_utf8_len is the length of `bytes` without a UTF-8 character cut off at the
+/// end, whose remaining bytes are carried over to the next write.
+fn complete_utf8_len(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(3) {
+        let start = bytes.len() - back;
+        let byte = bytes[start];
+        if byte & 0b1100_0000 == 0b1000_0000 {
+            continue;
+        }
+        let width = match byte {
+            0xf0.. => 4,
+            0xe0.. => 3,
+            0xc0.. => 2,
+            _ => 1,
+        };
+        return if width > back { start } else { bytes.len() };
+    }
+    bytes.len()
+}
+
+/// exit_code maps a child's exit status to the code the wrapper should exit with. A child
+/// killed by a signal yields 128 + the signal number, as a shell would report it.
+pub fn exit_code(status: &ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    1
+}
+
+/// Forwarding of SIGINT and SIGTERM to the wrapped command, which would otherwise keep
+/// running after the wrapper is stopped.
+#[cfg(unix)]
+mod signals {
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    const FORWARDED: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+    static CHILD: AtomicI32 = AtomicI32::new(0);
+
+    /// Restores the previous signal handlers when dropped.
+    pub struct Forwarding {
+        previous: Vec<(libc::c_int, libc::sigaction)>,
+    }
+
+    /// forward_to installs handlers passing SIGINT and SIGTERM on to process `child`.
+    pub fn forward_to(child: u32) -> Forwarding {
+        CHILD.store(child as i32, Ordering::SeqCst);
+        let mut previous = Vec::new();
+        for signal in FORWARDED {
+            // SAFETY: the handler only makes async-signal-safe calls, and the previous
+            // action is restored before the struct it is read into goes away.
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = forward as *const () as libc::sighandler_t;
+                action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                let mut old: libc::sigaction = std::mem::zeroed();
+                if libc::sigaction(signal, &action, &mut old) == 0 {
+                    previous.push((signal, old));
+                }
+            }
+        }
+        Forwarding { previous }
+    }
+
+    impl Drop for Forwarding {
+        fn drop(&mut self) {
+            for (signal, old) in &self.previous {
+                // SAFETY: `old` is the action sigaction returned for this signal.
+                unsafe {
+                    libc::sigaction(*signal, old, std::ptr::null_mut());
+                }
+            }
+            CHILD.store(0, Ordering::SeqCst);
+        }
+    }
+
+    extern "C" fn forward(
+        signal: libc::c_int,
+        info: *mut libc::siginfo_t,
+        _context: *mut libc::c_void,
+    ) {
+        // Signals the terminal sends, such as Ctrl-C, go to the whole foreground process
+        // group and have reached the command already; only those sent to the wrapper
+        // alone, with `kill`, are passed on.
+        let sender = sender_pid(info);
+        let child = CHILD.load(Ordering::SeqCst);
+        if child > 0 && sender != 0 {
+            // SAFETY: kill is async-signal-safe.
+            unsafe {
+                libc::kill(child, signal);
+            }
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn sender_pid(info: *mut libc::siginfo_t) -> libc::pid_t {
+        // SAFETY: the kernel passes a valid siginfo_t to SA_SIGINFO handlers.
+        unsafe { (*info).si_pid() }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn sender_pid(info: *mut libc::siginfo_t) -> libc::pid_t {
+        // SAFETY: the kernel passes a valid siginfo_t to SA_SIGINFO handlers.
+        unsafe { (*info).si_pid }
+    }
+}
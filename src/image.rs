@@ -0,0 +1,618 @@
+use crate::archive;
+use crate::error::{self, Error};
+use crate::report::{Finding, ScanStatus, SkipReason, SkippedFile};
+use crate::scanner::Scanner;
+use flate2::read::MultiGzDecoder;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// A layer file named `.wh.<name>` deletes `<name>` from the layers below.
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// A layer file named `.wh..wh..opq` hides everything the layers below put in its directory.
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// How many image indexes deep `index.json` is followed to find an image manifest.
+const MAX_INDEX_DEPTH: usize = 4;
+
+/// Where in an image a secret was found.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingOrigin {
+    /// A file in a layer.
+    Layer,
+    /// An environment variable in the image config.
+    Env,
+    /// A label in the image config.
+    Label,
+    /// The default `Entrypoint` or `Cmd` in the image config.
+    Command,
+    /// A build history entry, which records the instruction that built each layer.
+    History,
+}
+
+/// A layer of the image, numbered from the base layer up.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Layer {
+    pub index: usize,
+    pub digest: String,
+    /// The Dockerfile instruction that created the layer, from the build history.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+}
+
+/// A finding in an image, with the layer and build instruction it came from.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ImageFinding {
+    /// For layer files, `file_path` is the path inside the image. Config findings use
+    /// `config:Env`, `config:Labels`, `config:Entrypoint`, `config:Cmd` or
+    /// `config:history`, with the entry's position as `line_number` for lists.
+    #[serde(flatten)]
+    pub finding: Finding,
+    pub origin: FindingOrigin,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer_digest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    /// The later layer that deleted or replaced the file. The secret is gone from
+    /// containers run from the image, but anyone who pulls the image can still read it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removed_in_layer: Option<usize>,
+}
+
+/// The result of scanning an image.
+#[derive(Debug, Default)]
+pub struct ImageScan {
+    pub layers: Vec<Layer>,
+    pub findings: Vec<ImageFinding>,
+    /// Layer files read and scanned.
+    pub files_scanned: usize,
+    /// Layers and layer files that could not be scanned. Files are named
+    /// `<layer digest>!/<path>`.
+    pub skipped_files: Vec<SkippedFile>,
+}
+
+impl ImageScan {
+    /// unscanned_layers lists the layers that could not be scanned at all, such as
+    /// zstd-compressed ones. Secrets in them went unchecked.
+    pub fn unscanned_layers(&self) -> Vec<SkippedFile> {
+        self.skipped_files
+            .iter()
+            .filter(|skipped| self.layers.iter().any(|layer| layer.digest == skipped.path))
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct ImageReport<'a> {
+    status: ScanStatus,
+    image: &'a str,
+    layers: &'a [Layer],
+    findings: &'a [ImageFinding],
+    files_scanned: usize,
+    skipped_files: &'a [SkippedFile],
+    scan_time: String,
+}
+
+/// create_report builds the JSON report for `key-watch scan-image`.
+pub fn create_report(
+    image: &str,
+    scan: &ImageScan,
+    scan_time: String,
+) -> Result<String, serde_json::Error> {
+    let status = if scan.findings.is_empty() {
+        ScanStatus::Pass
+    } else {
+        ScanStatus::Fail
+    };
+    serde_json::to_string_pretty(&ImageReport {
+        status,
+        image,
+        layers: &scan.layers,
+        findings: &scan.findings,
+        files_scanned: scan.files_scanned,
+        skipped_files: &scan.skipped_files,
+        scan_time,
+    })
+}
+
+/// scan_image scans a container image saved with `docker save`, or an OCI image layout
+/// directory. Every file of every layer is scanned, including files later layers delete,
+/// along with the environment, labels, command and build history in the image config.
+/// Only the first image of a tarball or index is scanned; for a multi-platform index
+/// that is the first platform listed. Layer files larger than the scanner's maximum file
+/// size are skipped.
+pub fn scan_image(scanner: &Scanner, path: impl AsRef<Path>) -> error::Result<ImageScan> {
+    let path = path.as_ref();
+    let invalid = |message: String| Error::Image {
+        path: path.to_path_buf(),
+        message,
+    };
+    let files = ImageFiles::open(path)?;
+    let manifest = files.manifest().map_err(invalid)?;
+    let config = files.read_json(&manifest.config).map_err(invalid)?;
+
+    let history = history(&config);
+    let layers = layers(&manifest, &config, &history);
+    let mut scan = ImageScan::default();
+
+    let mut changes = Vec::new();
+    for (layer, blob) in layers.iter().zip(&manifest.layers) {
+        let mut layer_changes = LayerChanges::default();
+        if let Err(reason) = scan_layer(scanner, &files, blob, layer, &mut layer_changes, &mut scan)
+        {
+            scan.skipped_files.push(SkippedFile {
+                path: layer.digest.clone(),
+                reason,
+            });
+        }
+        changes.push(layer_changes);
+    }
+    for finding in &mut scan.findings {
+        if let Some(index) = finding.layer_index {
+            finding.removed_in_layer = (index + 1..changes.len()).find(|&later| {
+                // A secret inside an archive goes away with the archive file.
+                changes[later].removes(archive::outer_path(&finding.finding.file_path))
+            });
+        }
+    }
+
+    scan_config(scanner, &config, &history, &layers, &mut scan.findings);
+    scan.layers = layers;
+    Ok(scan)
+}
+
+/// Where an image's config and layers are stored.
+struct Manifest {
+    config: String,
+    /// File name of each layer blob, with its digest when the manifest gives one.
+    layers: Vec<(String, Option<String>)>,
+}
+
+/// The files of a saved image: an OCI layout directory, or a tarball indexed so each
+/// file inside can be read without unpacking the others.
+enum ImageFiles {
+    Dir(PathBuf),
+    Tarball {
+        path: PathBuf,
+        /// Offset and size of each file in the tarball.
+        entries: HashMap<String, (u64, u64)>,
+    },
+}
+
+impl ImageFiles {
+    fn open(path: &Path) -> error::Result<Self> {
+        let io_error = |source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if fs::metadata(path).map_err(io_error)?.is_dir() {
+            return Ok(Self::Dir(path.to_path_buf()));
+        }
+
+        let mut file = File::open(path).map_err(io_error)?;
+        let mut magic = [0; 2];
+        if file.read(&mut magic).map_err(io_error)? == 2 && magic == GZIP_MAGIC {
+            return Err(Error::Image {
+                path: path.to_path_buf(),
+                message: "compressed tarballs are not supported; decompress it first".into(),
+            });
+        }
+        file.rewind().map_err(io_error)?;
+
+        let mut archive = tar::Archive::new(file);
+        let mut entries = HashMap::new();
+        for entry in archive.entries_with_seek().map_err(io_error)? {
+            let entry = entry.map_err(io_error)?;
+            entries.insert(
+                normalize_path(&entry.path_bytes()),
+                (entry.raw_file_position(), entry.size()),
+            );
+        }
+        Ok(Self::Tarball {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        match self {
+            Self::Dir(dir) => is_relative(name) && dir.join(name).is_file(),
+            Self::Tarball { entries, .. } => entries.contains_key(name),
+        }
+    }
+
+    fn reader(&self, name: &str) -> io::Result<Box<dyn Read>> {
+        let missing = || io::Error::new(io::ErrorKind::NotFound, format!("{name} not found"));
+        match self {
+            Self::Dir(dir) => {
+                if !is_relative(name) {
+                    return Err(missing());
+                }
+                Ok(Box::new(File::open(dir.join(name))?))
+            }
+            Self::Tarball { path, entries } => {
+                let &(offset, size) = entries.get(name).ok_or_else(missing)?;
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                Ok(Box::new(file.take(size)))
+            }
+        }
+    }
+
+    fn read_json(&self, name: &str) -> Result<Value, String> {
+        let reader = self
+            .reader(name)
+            .map_err(|err| format!("cannot read {}: {}", name, err))?;
+        serde_json::from_reader(BufReader::new(reader))
+            .map_err(|err| format!("invalid {}: {}", name, err))
+    }
+
+    /// manifest finds the image in `docker save` output by its manifest.json, or in an OCI
+    /// layout by following index.json to an image manifest.
+    fn manifest(&self) -> Result<Manifest, String> {
+        if self.contains("manifest.json") {
+            let manifest = self.read_json("manifest.json")?;
+            let image = manifest.get(0).ok_or("manifest.json lists no images")?;
+            let config = image["Config"]
+                .as_str()
+                .ok_or("manifest.json has no Config")?;
+            let layers = image["Layers"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(|name| (name.to_string(), None))
+                .collect();
+            return Ok(Manifest {
+                config: config.to_string(),
+                layers,
+            });
+        }
+
+        if !self.contains("index.json") {
+            return Err(
+                "no manifest.json or index.json; expected a `docker save` tarball or an OCI layout"
+                    .to_string(),
+            );
+        }
+        let mut manifest = self.read_json("index.json")?;
+        for _ in 0..MAX_INDEX_DEPTH {
+            let Some(descriptors) = manifest["manifests"].as_array() else {
+                break;
+            };
+            let descriptor = descriptors
+                .iter()
+                .find(|descriptor| !is_attestation(descriptor))
+                .ok_or("the image index lists no images")?;
+            let digest = descriptor["digest"]
+                .as_str()
+                .ok_or("an image index entry has no digest")?;
+            manifest = self.read_json(&blob_name(digest)?)?;
+        }
+
+        let config = manifest["config"]["digest"]
+            .as_str()
+            .ok_or("the image manifest has no config")?;
+        let layers = manifest["layers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|layer| layer["digest"].as_str())
+            .map(|digest| Ok((blob_name(digest)?, Some(digest.to_string()))))
+            .collect::<Result<_, String>>()?;
+        Ok(Manifest {
+            config: blob_name(config)?,
+            layers,
+        })
+    }
+}
+
+/// Build attestations are listed in image indexes next to the images they describe.
+fn is_attestation(descriptor: &Value) -> bool {
+    descriptor["annotations"]["vnd.docker.reference.type"] == "attestation-manifest"
+        || descriptor["platform"]["os"] == "unknown"
+}
+
+/// blob_name maps a digest such as `sha256:ab12…` to its file, `blobs/sha256/ab12…`.
+fn blob_name(digest: &str) -> Result<String, String> {
+    match digest.split_once(':') {
+        Some((algorithm, hex))
+            if !algorithm.is_empty()
+                && !hex.is_empty()
+                && algorithm.bytes().all(|byte| byte.is_ascii_alphanumeric())
+                && hex.bytes().all(|byte| byte.is_ascii_alphanumeric()) =>
+        {
+            Ok(format!("blobs/{}/{}", algorithm, hex))
+        }
+        _ => Err(format!("invalid digest '{}'", digest)),
+    }
+}
+
+/// is_relative rejects paths from manifests that would escape the layout directory.
+fn is_relative(name: &str) -> bool {
+    Path::new(name)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn normalize_path(bytes: &[u8]) -> String {
+    let path = String::from_utf8_lossy(bytes);
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+    path.trim_end_matches('/').to_string()
+}
+
+/// A build history entry; only entries that are not `empty_layer` created a layer.
+struct HistoryEntry {
+    instruction: Option<String>,
+    empty_layer: bool,
+}
+
+fn history(config: &Value) -> Vec<HistoryEntry> {
+    config["history"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|entry| HistoryEntry {
+            instruction: entry["created_by"].as_str().map(instruction),
+            empty_layer: entry["empty_layer"].as_bool().unwrap_or(false),
+        })
+        .collect()
+}
+
+/// instruction turns a history `created_by` back into the Dockerfile instruction:
+/// the classic builder records `/bin/sh -c #(nop) COPY …` and `/bin/sh -c <command>`,
+/// BuildKit `COPY … # buildkit`.
+fn instruction(created_by: &str) -> String {
+    let created_by = created_by.trim();
+    let created_by = created_by
+        .strip_suffix("# buildkit")
+        .unwrap_or(created_by)
+        .trim_end();
+    if let Some(rest) = created_by.strip_prefix("/bin/sh -c #(nop)") {
+        rest.trim().to_string()
+    } else if let Some(rest) = created_by.strip_prefix("/bin/sh -c ") {
+        format!("RUN {}", rest.trim())
+    } else {
+        created_by.to_string()
+    }
+}
+
+/// layers names each layer by digest and pairs it with the history entry that built it.
+/// Layer digests come from the manifest, the blob name, or the config's `diff_ids`.
+fn layers(manifest: &Manifest, config: &Value, history: &[HistoryEntry]) -> Vec<Layer> {
+    let diff_ids: Vec<&str> = config["rootfs"]["diff_ids"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let built: Vec<&HistoryEntry> = history.iter().filter(|entry| !entry.empty_layer).collect();
+    // Without one history entry per layer, the pairing would be a guess.
+    let attributed = built.len() == manifest.layers.len();
+
+    manifest
+        .layers
+        .iter()
+        .enumerate()
+        .map(|(index, (name, digest))| Layer {
+            index,
+            digest: digest
+                .clone()
+                .or_else(|| digest_of_blob(name))
+                .or_else(|| diff_ids.get(index).map(|id| id.to_string()))
+                .unwrap_or_else(|| name.clone()),
+            created_by: attributed
+                .then(|| built[index].instruction.clone())
+                .flatten(),
+        })
+        .collect()
+}
+
+fn digest_of_blob(name: &str) -> Option<String> {
+    let (algorithm, hex) = name.strip_prefix("blobs/")?.split_once('/')?;
+    Some(format!("{}:{}", algorithm, hex))
+}
+
+/// What a layer changed, used to tell which files later layers removed.
+#[derive(Default)]
+struct LayerChanges {
+    /// Regular files the layer added or replaced.
+    files: HashSet<String>,
+    /// Paths the layer deleted with whiteout files.
+    deleted: Vec<String>,
+    /// Directories whose earlier content the layer hid with opaque whiteouts.
+    opaque: Vec<String>,
+}
+
+impl LayerChanges {
+    fn removes(&self, path: &str) -> bool {
+        self.files.contains(path)
+            || self
+                .deleted
+                .iter()
+                .chain(&self.opaque)
+                .any(|dir| is_within(path, dir))
+    }
+}
+
+fn is_within(path: &str, dir: &str) -> bool {
+    dir.is_empty()
+        || path == dir
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn scan_layer(
+    scanner: &Scanner,
+    files: &ImageFiles,
+    blob: &(String, Option<String>),
+    layer: &Layer,
+    changes: &mut LayerChanges,
+    scan: &mut ImageScan,
+) -> Result<(), SkipReason> {
+    let skip = |err: io::Error| SkipReason::from_io_error(&err);
+    let mut reader = BufReader::new(files.reader(&blob.0).map_err(skip)?);
+    let head = reader.fill_buf().map_err(skip)?;
+    let reader: Box<dyn Read> = if head.starts_with(GZIP_MAGIC) {
+        Box::new(MultiGzDecoder::new(reader))
+    } else if head.starts_with(ZSTD_MAGIC) {
+        return Err(SkipReason::UnsupportedCompression);
+    } else {
+        Box::new(reader)
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().map_err(skip)? {
+        let mut entry = entry.map_err(skip)?;
+        let path = normalize_path(&entry.path_bytes());
+        let (dir, file_name) = match path.rsplit_once('/') {
+            Some((dir, file_name)) => (dir, file_name),
+            None => ("", path.as_str()),
+        };
+        if file_name == OPAQUE_WHITEOUT {
+            changes.opaque.push(dir.to_string());
+            continue;
+        }
+        if let Some(deleted) = file_name.strip_prefix(WHITEOUT_PREFIX) {
+            let deleted = match dir {
+                "" => deleted.to_string(),
+                dir => format!("{}/{}", dir, deleted),
+            };
+            changes.deleted.push(deleted);
+            continue;
+        }
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        changes.files.insert(path.clone());
+        if scanner
+            .max_file_size()
+            .is_some_and(|max_file_size| entry.size() > max_file_size)
+        {
+            scan.skipped_files.push(SkippedFile {
+                path: format!("{}!/{}", layer.digest, path),
+                reason: SkipReason::TooLarge,
+            });
+            continue;
+        }
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).map_err(skip)?;
+        scan.files_scanned += 1;
+        scan.findings.extend(
+            scanner
+                .scan_bytes(&path, &content)
+                .into_iter()
+                .map(|finding| ImageFinding {
+                    finding,
+                    origin: FindingOrigin::Layer,
+                    layer_index: Some(layer.index),
+                    layer_digest: Some(layer.digest.clone()),
+                    created_by: layer.created_by.clone(),
+                    removed_in_layer: None,
+                }),
+        );
+    }
+    Ok(())
+}
+
+/// scan_config scans the config's environment, labels and command, attributing each
+/// finding to the last instruction that mentions its key, and the build history,
+/// attributing findings there to the layer the instruction built.
+fn scan_config(
+    scanner: &Scanner,
+    config: &Value,
+    history: &[HistoryEntry],
+    layers: &[Layer],
+    findings: &mut Vec<ImageFinding>,
+) {
+    let settings = &config["config"];
+    let strings = |value: &Value| -> Vec<String> {
+        value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect()
+    };
+    let env = strings(&settings["Env"]);
+    let labels: Vec<String> = settings["Labels"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| format!("{}={}", key, value.as_str().unwrap_or_default()))
+        .collect();
+    let entrypoint = strings(&settings["Entrypoint"]).join(" ");
+    let cmd = strings(&settings["Cmd"]).join(" ");
+
+    let sections = [
+        ("config:Env", FindingOrigin::Env, "ENV", env),
+        ("config:Labels", FindingOrigin::Label, "LABEL", labels),
+        (
+            "config:Entrypoint",
+            FindingOrigin::Command,
+            "ENTRYPOINT",
+            vec![entrypoint],
+        ),
+        ("config:Cmd", FindingOrigin::Command, "CMD", vec![cmd]),
+    ];
+    for (name, origin, keyword, entries) in sections {
+        for (position, entry) in entries.iter().enumerate() {
+            let key = match origin {
+                FindingOrigin::Command => "",
+                _ => entry.split('=').next().unwrap_or_default(),
+            };
+            let created_by = history
+                .iter()
+                .rev()
+                .filter_map(|entry| entry.instruction.as_deref())
+                .find(|instruction| instruction.starts_with(keyword) && instruction.contains(key))
+                .map(str::to_string);
+            for mut finding in scanner.scan_bytes(name, entry.as_bytes()) {
+                finding.line_number = position + 1;
+                findings.push(ImageFinding {
+                    finding,
+                    origin,
+                    layer_index: None,
+                    layer_digest: None,
+                    created_by: created_by.clone(),
+                    removed_in_layer: None,
+                });
+            }
+        }
+    }
+
+    let built = history.iter().filter(|entry| !entry.empty_layer).count();
+    let mut next_layer = 0;
+    for entry in history {
+        let layer = (!entry.empty_layer && built == layers.len()).then(|| &layers[next_layer]);
+        if !entry.empty_layer {
+            next_layer += 1;
+        }
+        let Some(ref instruction) = entry.instruction else {
+            continue;
+        };
+        for finding in scanner.scan_bytes("config:history", instruction.as_bytes()) {
+            findings.push(ImageFinding {
+                finding,
+                origin: FindingOrigin::History,
+                layer_index: layer.map(|layer| layer.index),
+                layer_digest: layer.map(|layer| layer.digest.clone()),
+                created_by: Some(instruction.clone()),
+                removed_in_layer: None,
+            });
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use crate::report::Severity;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// A gitleaks construct that could not be carried over exactly.
+#[derive(Debug)]
+pub struct ImportWarning {
+    pub rule: String,
+    pub message: String,
+}
+
+/// Outcome of converting a foreign rule file into detectors.toml contents.
+pub struct ImportResult {
+    pub detectors_toml: String,
+    pub imported: usize,
+    pub skipped: usize,
+    pub warnings: Vec<ImportWarning>,
+}
+
+/// This structure mirrors the parts of a gitleaks.toml file KeyWatch understands.
+#[derive(Deserialize)]
+struct GitleaksConfig {
+    #[serde(default)]
+    rules: Vec<GitleaksRule>,
+    allowlist: Option<GitleaksAllowlist>,
+    #[serde(default)]
+    allowlists: Vec<GitleaksAllowlist>,
+    extend: Option<toml::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GitleaksRule {
+    id: String,
+    description: Option<String>,
+    regex: Option<String>,
+    secret_group: Option<u32>,
+    entropy: Option<f64>,
+    #[serde(default)]
+    keywords: Vec<String>,
+    path: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    allowlist: Option<GitleaksAllowlist>,
+    #[serde(default)]
+    allowlists: Vec<GitleaksAllowlist>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GitleaksAllowlist {
+    condition: Option<String>,
+    #[serde(default)]
+    regexes: Vec<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+    #[serde(default)]
+    stopwords: Vec<String>,
+    #[serde(default)]
+    commits: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ImportedConfig {
+    detectors: Vec<ImportedDetector>,
+}
+
+#[derive(Serialize)]
+struct ImportedDetector {
+    name: String,
+    id: String,
+    pattern: String,
+    finding_type: String,
+    severity: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    description: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    paths: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    exclude_paths: Vec<String>,
+}
+
+/// import_gitleaks_file reads a gitleaks.toml file and converts its rules.
+pub fn import_gitleaks_file(path: &Path, severity: Severity) -> Result<ImportResult, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    import_gitleaks(&contents, severity)
+        .map_err(|err| format!("Failed to import {}: {}", path.display(), err))
+}
+
+/// import_gitleaks converts gitleaks `[[rules]]` into detectors.toml contents. gitleaks has no
+/// severities, so every imported detector gets `severity`. Constructs KeyWatch cannot express
+/// are dropped with a warning; rules that cannot work at all are skipped.
+pub fn import_gitleaks(contents: &str, severity: Severity) -> Result<ImportResult, String> {
+    let config: GitleaksConfig = toml::from_str(contents).map_err(|err| err.to_string())?;
+    let mut warnings = Vec::new();
+    let mut warn = |rule: &str, message: String| {
+        warnings.push(ImportWarning {
+            rule: rule.to_string(),
+            message,
+        })
+    };
+
+    if config.extend.is_some() {
+        warn(
+            "<file>",
+            "[extend] is ignored; KeyWatch's built-in detectors already apply underneath imported ones"
+                .to_string(),
+        );
+    }
+
+    let global_allowlists: Vec<GitleaksAllowlist> = config
+        .allowlist
+        .into_iter()
+        .chain(config.allowlists)
+        .collect();
+    let mut global_excludes = Vec::new();
+    for allowlist in &global_allowlists {
+        global_excludes.extend(convert_allowlist(
+            allowlist,
+            "<global allowlist>",
+            &mut warn,
+        ));
+    }
+
+    let mut detectors: Vec<ImportedDetector> = Vec::new();
+    let mut skipped = 0;
+
+    for rule in config.rules {
+        let id = rule.id.as_str();
+        let name = detector_name(id);
+
+        if detectors.iter().any(|detector| detector.name == name) {
+            warn(
+                id,
+                format!("skipped: detector name `{name}` is already used by another rule"),
+            );
+            skipped += 1;
+            continue;
+        }
+
+        let Some(pattern) = rule.regex else {
+            warn(
+                id,
+                "skipped: path-only rules (no `regex`) report files, not secrets".to_string(),
+            );
+            skipped += 1;
+            continue;
+        };
+
+        if let Err(err) = Regex::new(&pattern) {
+            warn(id, format!("skipped: regex does not compile: {err}"));
+            skipped += 1;
+            continue;
+        }
+
+        if (pattern.contains("\\s\\S") || pattern.contains("\\n")) && !pattern.contains("(?s)") {
+            warn(
+                id,
+                "regex looks like it spans lines, but KeyWatch matches it line by line; add `(?s)` to run it on whole files"
+                    .to_string(),
+            );
+        }
+
+        if let Some(group) = rule.secret_group {
+            warn(
+                id,
+                format!("secretGroup = {group} is ignored; findings report the whole match"),
+            );
+        }
+
+        if let Some(entropy) = rule.entropy {
+            warn(
+                id,
+                format!("entropy = {entropy} is ignored; expect more low-entropy false positives"),
+            );
+        }
+
+        if !rule.keywords.is_empty() {
+            warn(
+                id,
+                "keywords are ignored; KeyWatch has no keyword pre-filter, so only the regex decides matches"
+                    .to_string(),
+            );
+        }
+
+        let mut paths = Vec::new();
+        if let Some(ref path) = rule.path {
+            match regex_to_glob(path) {
+                Some(glob) => paths.push(glob),
+                None => warn(
+                    id,
+                    format!(
+                        "path `{path}` has no glob equivalent and is ignored; the detector runs on every file"
+                    ),
+                ),
+            }
+        }
+
+        let mut exclude_paths = global_excludes.clone();
+        for allowlist in rule.allowlist.iter().chain(&rule.allowlists) {
+            exclude_paths.extend(convert_allowlist(allowlist, id, &mut warn));
+        }
+
+        detectors.push(ImportedDetector {
+            name,
+            id: id.to_string(),
+            pattern,
+            finding_type: rule.description.unwrap_or_else(|| id.to_string()),
+            severity: severity.as_str().to_string(),
+            tags: rule.tags,
+            description: format!("Imported from gitleaks rule '{id}'"),
+            paths,
+            exclude_paths,
+        });
+    }
+
+    let imported = detectors.len();
+    let body = toml::to_string(&ImportedConfig { detectors })
+        .map_err(|err| format!("Failed to serialize detectors: {}", err))?;
+
+    Ok(ImportResult {
+        detectors_toml: format!("# Imported from gitleaks by `key-watch rules import`\n\n{body}"),
+        imported,
+        skipped,
+        warnings,
+    })
+}
+
+/// convert_allowlist turns allowlisted paths into `exclude_paths` globs and warns about
+/// everything else, since KeyWatch detectors cannot allowlist by content or commit.
+fn convert_allowlist(
+    allowlist: &GitleaksAllowlist,
+    rule: &str,
+    warn: &mut impl FnMut(&str, String),
+) -> Vec<String> {
+    if !allowlist.regexes.is_empty() {
+        warn(
+            rule,
+            format!(
+                "allowlist regexes are ignored ({} pattern(s)); matches they would suppress are reported",
+                allowlist.regexes.len()
+            ),
+        );
+    }
+    if !allowlist.stopwords.is_empty() {
+        warn(rule, "allowlist stopwords are ignored".to_string());
+    }
+    if !allowlist.commits.is_empty() {
+        warn(
+            rule,
+            "allowlist commits are ignored; KeyWatch scans files, not history".to_string(),
+        );
+    }
+
+    let is_and = allowlist
+        .condition
+        .as_deref()
+        .is_some_and(|condition| condition.eq_ignore_ascii_case("AND"));
+    let has_other_conditions = !allowlist.regexes.is_empty() || !allowlist.stopwords.is_empty();
+    if is_and && has_other_conditions && !allowlist.paths.is_empty() {
+        warn(
+            rule,
+            "allowlist paths combined with condition = \"AND\" are ignored; excluding the paths outright would hide real findings"
+                .to_string(),
+        );
+        return Vec::new();
+    }
+
+    let mut globs = Vec::new();
+    for path in &allowlist.paths {
+        match regex_to_glob(path) {
+            Some(glob) => globs.push(glob),
+            None => warn(
+                rule,
+                format!("allowlist path `{path}` has no glob equivalent and is ignored"),
+            ),
+        }
+    }
+    globs
+}
+
+/// regex_to_glob converts the simple path regexes gitleaks configs use, such as
+/// `\.lock$`, `^vendor/.*$` or `(^|/)package-lock\.json$`, into globs. Anything
+/// more involved returns None.
+pub fn regex_to_glob(path_regex: &str) -> Option<String> {
+    let body = path_regex.strip_suffix('$')?;
+    let (body, anchored) = if let Some(rest) = body.strip_prefix("(^|/)") {
+        (rest, true)
+    } else if let Some(rest) = body.strip_prefix('^') {
+        (rest, true)
+    } else {
+        (body, false)
+    };
+
+    let mut glob = String::new();
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                escaped @ ('.' | '/' | '-' | '_') => glob.push(escaped),
+                _ => return None,
+            },
+            '.' if chars.peek() == Some(&'*') => {
+                chars.next();
+                glob.push('*');
+            }
+            c if c.is_ascii_alphanumeric() || matches!(c, '/' | '-' | '_') => glob.push(c),
+            _ => return None,
+        }
+    }
+
+    if glob.is_empty() {
+        return None;
+    }
+    if !anchored && !glob.starts_with('*') {
+        glob.insert(0, '*');
+    }
+    Some(glob)
+}
+
+/// detector_name turns a gitleaks id like `aws-access-token` into `AwsAccessTokenDetector`.
+fn detector_name(id: &str) -> String {
+    let mut name: String = id
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    name.push_str("Detector");
+    name
+}
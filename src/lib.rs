@@ -1,23 +1,42 @@
+pub mod archive;
+pub mod binary;
+pub mod cache;
 pub mod cli;
+pub mod config;
 pub mod detector;
+pub mod encoding;
+pub mod error;
+pub mod exec;
 pub mod hooks;
+pub mod image;
+pub mod import;
+pub mod lsp;
+pub mod policy;
+pub mod redact;
 pub mod report;
+pub mod rules;
 pub mod scanner;
+pub mod serve;
+pub mod trust;
 pub mod utils;
+pub mod watch;
 
 use clap::Parser;
 use cli::{
-    CliOptions, Command, ExitMode, HookAction, HookInstallArgs, HookType, HookUninstallArgs,
-    ScanArgs, Shell,
+    CliOptions, Command, ConfigAction, ConfigTrustArgs, ConfigUntrustArgs, ExecArgs, HookAction,
+    HookInstallArgs, HookType, HookUninstallArgs, LspArgs, PolicyArgs, RedactArgs, RuleFormat,
+    RulesAction, RulesImportArgs, RulesLintArgs, RulesListArgs, RulesShowArgs, RulesTestArgs,
+    ScanArgs, ScanImageArgs, ServeArgs, Shell, WatchArgs,
 };
-use report::Finding;
+use policy::{EXIT_CODE_FINDINGS, FailurePolicy};
+use report::{Finding, SkippedFile};
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command as ProcessCommand;
 use std::time::Instant;
 
-use crate::report::Severity;
+pub use error::Error;
 pub use hooks::{generate_pre_commit_hook, generate_pre_push_hook};
 
 pub const EXIT_CODE_RUNTIME_ERROR: i32 = 2;
@@ -28,10 +47,27 @@ pub fn run_cli() -> Result<(), String> {
 
     match options.command {
         Command::Scan(args) => run_scan_command(&args),
+        Command::ScanImage(args) => run_scan_image(&args),
+        Command::Watch(args) => run_watch(&args),
+        Command::Redact(args) => run_redact(&args),
+        Command::Lsp(args) => run_lsp(&args),
+        Command::Exec(args) => run_exec(&args),
+        Command::Serve(args) => run_serve(&args),
         Command::Hook(args) => match args.action {
             HookAction::Install(install_args) => install_hook(&install_args),
             HookAction::Uninstall(uninstall_args) => uninstall_hook(&uninstall_args),
         },
+        Command::Rules(args) => match args.action {
+            RulesAction::Test(test_args) => run_rules_test(&test_args),
+            RulesAction::Lint(lint_args) => run_rules_lint(&lint_args),
+            RulesAction::List(list_args) => run_rules_list(&list_args),
+            RulesAction::Import(import_args) => run_rules_import(&import_args),
+            RulesAction::Show(show_args) => run_rules_show(&show_args),
+        },
+        Command::Config(args) => match args.action {
+            ConfigAction::Trust(trust_args) => trust_config(&trust_args),
+            ConfigAction::Untrust(untrust_args) => untrust_config(&untrust_args),
+        },
         Command::Init { shell } => {
             print_shell_init(&shell);
             Ok(())
@@ -43,16 +79,31 @@ pub fn run_cli() -> Result<(), String> {
 fn run_scan_command(args: &ScanArgs) -> Result<(), String> {
     let start = Instant::now();
 
-    let (findings, scan_metadata) = scanner::run_scan(args)?;
-    let elapsed = start.elapsed();
-    let scan_time = format!(
-        "{}.{:01}s",
-        elapsed.as_secs(),
-        elapsed.subsec_millis() / 100
-    );
+    let (findings, scan_metadata) = if args.reads_stdin() {
+        if args.paths.len() > 1 {
+            return Err("`-` (standard input) cannot be combined with other paths".to_string());
+        }
+        if args.cache {
+            return Err("--cache does not apply to standard input".to_string());
+        }
+        scanner::run_stream_scan(args, std::io::stdin().lock(), |finding| {
+            eprintln!("{}", describe_finding(finding));
+        })
+    } else if args.stdin_filename.is_some() {
+        return Err("--stdin-filename only applies when scanning `-`".to_string());
+    } else {
+        scanner::run_scan(args)
+    }
+    .map_err(|err| err.to_string())?;
+    print_warnings(&scan_metadata.warnings);
+    let scan_time = report::format_scan_time(start.elapsed());
     let severity_counts = report::get_severity_counts(&findings);
-    let exit_code = calculate_exit_code(&findings, &args.exit_mode);
+    let exit_code = calculate_exit_code(&findings, &args.policy.failure_policy());
+    let exit_code = skipped_exit_code(&args.policy, &scan_metadata.skipped_files, exit_code);
     let findings_count = findings.len();
+    let cache_stats = scan_metadata.cache;
+    let skipped_count = scan_metadata.skipped_files.len();
+    let skipped_summary = report::skipped_summary(&scan_metadata.skipped_files);
     let report_json = report::create_report(findings, scan_metadata, scan_time)
         .map_err(|err| format!("Failed to serialize report: {}", err))?;
 
@@ -62,8 +113,21 @@ fn run_scan_command(args: &ScanArgs) -> Result<(), String> {
         println!("No secrets found.");
     } else {
         println!(
-            "WARNING: {} potential secret(s) detected (HIGH: {}, MEDIUM: {}, LOW: {})",
-            findings_count, severity_counts.0, severity_counts.1, severity_counts.2
+            "WARNING: {} potential secret(s) detected ({})",
+            findings_count, severity_counts
+        );
+    }
+    if skipped_count > 0 && !args.verbose {
+        println!("Skipped {} file(s): {}", skipped_count, skipped_summary);
+    }
+    if let Some(stats) = cache_stats
+        && !args.verbose
+    {
+        println!(
+            "Cache: {} of {} file(s) unchanged ({:.0}% hits)",
+            stats.hits,
+            stats.hits + stats.misses,
+            stats.hit_ratio() * 100.0
         );
     }
 
@@ -75,6 +139,654 @@ fn run_scan_command(args: &ScanArgs) -> Result<(), String> {
     std::process::exit(exit_code);
 }
 
+/// skipped_exit_code fails a scan that skipped files when `--fail-on-skipped` is set,
+/// keeping any failing exit code the findings already produced.
+fn skipped_exit_code(policy: &PolicyArgs, skipped: &[SkippedFile], exit_code: i32) -> i32 {
+    if !policy.fail_on_skipped || skipped.is_empty() {
+        return exit_code;
+    }
+    eprintln!(
+        "ERROR: {} file(s) could not be scanned ({}); failing because of --fail-on-skipped",
+        skipped.len(),
+        report::skipped_summary(skipped)
+    );
+    if exit_code == 0 {
+        EXIT_CODE_FINDINGS
+    } else {
+        exit_code
+    }
+}
+
+fn run_scan_image(args: &ScanImageArgs) -> Result<(), String> {
+    let start = Instant::now();
+    let mut builder = scanner::Scanner::builder();
+    if let Some(ref profile) = args.profile {
+        builder = builder.profile(profile);
+    }
+    if let Some(max_file_size) = args.max_file_size {
+        builder = builder.max_file_size(max_file_size);
+    }
+    let scanner = build_scanner(builder)?;
+
+    let scan = image::scan_image(&scanner, &args.image).map_err(|err| err.to_string())?;
+    let scan_time = report::format_scan_time(start.elapsed());
+    let findings: Vec<Finding> = scan
+        .findings
+        .iter()
+        .map(|finding| finding.finding.clone())
+        .collect();
+    let exit_code = calculate_exit_code(&findings, &args.policy.failure_policy());
+    let mut exit_code = skipped_exit_code(&args.policy, &scan.skipped_files, exit_code);
+    let unscanned = scan.unscanned_layers();
+    if !unscanned.is_empty() && !args.allow_unscanned_layers {
+        eprintln!(
+            "ERROR: {} layer(s) could not be scanned ({}); pass --allow-unscanned-layers to accept that",
+            unscanned.len(),
+            report::skipped_summary(&unscanned)
+        );
+        if exit_code == 0 {
+            exit_code = EXIT_CODE_FINDINGS;
+        }
+    }
+    let report_json = image::create_report(&args.image, &scan, scan_time)
+        .map_err(|err| format!("Failed to serialize report: {}", err))?;
+
+    if args.verbose {
+        println!("{report_json}");
+    } else {
+        for finding in &scan.findings {
+            println!("FOUND     {}", describe_finding(&finding.finding));
+            if let (Some(index), Some(digest)) = (finding.layer_index, &finding.layer_digest) {
+                println!("          layer {} ({})", index, digest);
+            }
+            if let Some(ref created_by) = finding.created_by {
+                println!("          created by: {}", created_by);
+            }
+            if let Some(removed_in) = finding.removed_in_layer {
+                println!(
+                    "          removed in layer {}, but still in the image",
+                    removed_in
+                );
+            }
+        }
+        if findings.is_empty() {
+            println!(
+                "No secrets found in {} layer(s) and the image config.",
+                scan.layers.len()
+            );
+        } else {
+            println!(
+                "WARNING: {} potential secret(s) detected ({})",
+                findings.len(),
+                report::get_severity_counts(&findings)
+            );
+        }
+        if !scan.skipped_files.is_empty() {
+            println!(
+                "Skipped {} file(s): {}",
+                scan.skipped_files.len(),
+                report::skipped_summary(&scan.skipped_files)
+            );
+        }
+    }
+
+    if let Some(ref output_path) = args.output {
+        utils::write_to_file(output_path, &report_json)
+            .map_err(|err| format!("Failed to write report to '{}': {}", output_path, err))?;
+    }
+    std::process::exit(exit_code);
+}
+
+fn run_watch(args: &WatchArgs) -> Result<(), String> {
+    let mut builder = scanner::Scanner::builder();
+    if let Some(ref profile) = args.profile {
+        builder = builder.profile(profile);
+    }
+    for pattern in args.exclude.iter().flat_map(|exclude| exclude.split(',')) {
+        if !pattern.trim().is_empty() {
+            builder = builder.exclude(pattern.trim());
+        }
+    }
+    let scanner = build_scanner(builder)?;
+
+    let mut session =
+        watch::WatchSession::new(scanner, &args.paths).map_err(|err| err.to_string())?;
+    let watcher =
+        watch::ChangeWatcher::start(session.roots(), args.poll).map_err(|err| err.to_string())?;
+    print_warnings(watcher.warnings());
+
+    for finding in session.findings() {
+        println!("FOUND     {}", describe_finding(finding));
+    }
+    println!(
+        "Watching {} path(s) with {} finding(s); press Ctrl-C to stop",
+        session.roots().len(),
+        session.findings().count()
+    );
+
+    watcher.run(
+        std::time::Duration::from_millis(args.debounce),
+        |changed| {
+            for change in session.rescan(&changed) {
+                match change {
+                    watch::FindingChange::New(finding) => {
+                        println!("NEW       {}", describe_finding(&finding));
+                    }
+                    watch::FindingChange::Resolved(finding) => {
+                        println!("RESOLVED  {}", describe_finding(&finding));
+                    }
+                }
+            }
+        },
+        |err| eprintln!("WARNING: watch error: {}", err),
+    );
+    Ok(())
+}
+
+fn run_lsp(args: &LspArgs) -> Result<(), String> {
+    let mut builder = scanner::Scanner::builder();
+    if let Some(ref profile) = args.profile {
+        builder = builder.profile(profile);
+    }
+    let scanner = build_scanner(builder)?;
+    lsp::run_stdio(scanner)
+}
+
+fn run_serve(args: &ServeArgs) -> Result<(), String> {
+    let token = match args.token_file {
+        Some(ref path) => Some(
+            fs::read_to_string(path)
+                .map_err(|err| format!("Failed to read token file '{}': {}", path, err))?
+                .trim()
+                .to_string(),
+        ),
+        None => env::var(serve::TOKEN_ENV).ok(),
+    }
+    .filter(|token| !token.is_empty());
+
+    let mut builder = scanner::Scanner::builder();
+    if let Some(ref profile) = args.profile {
+        builder = builder.profile(profile);
+    }
+    let scanner = build_scanner(builder)?;
+    let options = serve::ServeOptions {
+        max_body_bytes: args.max_body_bytes,
+        token,
+        max_connections: args.max_connections,
+        scan_root: current_dir_root(),
+        ..serve::ServeOptions::default()
+    };
+    let protected = options.token.is_some();
+    let server =
+        serve::Server::bind(&args.listen, scanner, options).map_err(|err| err.to_string())?;
+    let address = server
+        .local_addr()
+        .map_err(|err| format!("Failed to read listen address: {}", err))?;
+
+    if !protected && !address.ip().is_loopback() {
+        eprintln!(
+            "WARNING: serving on {} without a token; set {} or pass --token-file",
+            address,
+            serve::TOKEN_ENV
+        );
+    }
+    println!("Listening on http://{}", address);
+    server.run();
+    Ok(())
+}
+
+/// build_scanner builds the scanner for a command and prints the warnings raised while
+/// loading its detectors.
+fn build_scanner(builder: scanner::ScannerBuilder) -> Result<scanner::Scanner, String> {
+    let scanner = builder.build().map_err(|err| err.to_string())?;
+    print_warnings(scanner.warnings());
+    Ok(scanner)
+}
+
+/// current_dir_root is the working directory as a scan root, for commands that read
+/// files or names without walking a directory.
+fn current_dir_root() -> Option<String> {
+    env::current_dir()
+        .ok()
+        .map(|dir| dir.to_string_lossy().into_owned())
+}
+
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("WARNING: {warning}");
+    }
+}
+
+fn describe_finding(finding: &Finding) -> String {
+    let location = match finding.byte_offset {
+        Some(offset) => format!("{}@{}", finding.file_path, offset),
+        None => format!("{}:{}", finding.file_path, finding.line_number),
+    };
+    format!(
+        "{}: {} {} ({})",
+        location, finding.severity, finding.finding_type, finding.plugin_name
+    )
+}
+
+fn redaction_setup(
+    profile: Option<&str>,
+    mask: Option<&str>,
+) -> Result<(scanner::Scanner, redact::Replacement), String> {
+    let mut builder = scanner::Scanner::builder();
+    if let Some(profile) = profile {
+        builder = builder.profile(profile);
+    }
+    let scanner = build_scanner(builder)?;
+    let replacement = match mask {
+        Some(mask) => redact::Replacement::Mask(mask.to_string()),
+        None => redact::Replacement::Token,
+    };
+    Ok((scanner, replacement))
+}
+
+fn run_exec(args: &ExecArgs) -> Result<(), String> {
+    let (scanner, replacement) = redaction_setup(args.profile.as_deref(), args.mask.as_deref())?;
+    let outcome =
+        exec::run_masked(&scanner, &args.command, &replacement).map_err(|err| err.to_string())?;
+
+    let mut exit_code = exec::exit_code(&outcome.status);
+    if outcome.redactions > 0 {
+        eprintln!(
+            "WARNING: key-watch masked {} secret(s) in the output of '{}'",
+            outcome.redactions, args.command[0]
+        );
+        if args.fail_on_leak && exit_code == 0 {
+            exit_code = EXIT_CODE_FINDINGS;
+        }
+    }
+    std::process::exit(exit_code);
+}
+
+fn run_redact(args: &RedactArgs) -> Result<(), String> {
+    let (scanner, replacement) = redaction_setup(args.profile.as_deref(), args.mask.as_deref())?;
+
+    if args.paths.is_empty() || args.paths[0] == "-" {
+        let name = args
+            .stdin_filename
+            .as_deref()
+            .unwrap_or(scanner::STDIN_NAME);
+        let redactions = redact::redact_stream(
+            &scanner,
+            name,
+            std::io::stdin().lock(),
+            std::io::stdout().lock(),
+            &replacement,
+        )
+        .map_err(|err| err.to_string())?;
+        eprintln!("Redacted {} secret(s)", redactions);
+        return Ok(());
+    }
+
+    // Detector path globs relative to a root match against the working directory, as
+    // they would for `scan .`.
+    let roots = [current_dir_root()];
+    let mut redactions = 0;
+    let mut files_changed = 0;
+    for path in &args.paths {
+        let bytes = fs::read(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+        let Ok(decoded) = encoding::decode(&bytes) else {
+            eprintln!("WARNING: skipping binary file {}", path);
+            continue;
+        };
+
+        let redacted = redact::redact_text(&scanner, path, &roots, &decoded.text, &replacement);
+        redactions += redacted.redactions;
+        if !args.in_place {
+            print!("{}", redacted.content);
+            continue;
+        }
+        if redacted.redactions == 0 {
+            continue;
+        }
+        // Rewritten files keep their encoding, unless that would corrupt other content.
+        let Some(encoded) = decoded.encode(&redacted.content) else {
+            eprintln!(
+                "WARNING: not rewriting {}: its {} content cannot be written back unchanged",
+                path, decoded.encoding
+            );
+            continue;
+        };
+
+        let backup_path = format!("{}{}", path, args.backup_suffix);
+        fs::copy(path, &backup_path)
+            .map_err(|err| format!("Failed to back up {} to {}: {}", path, backup_path, err))?;
+        fs::write(path, encoded).map_err(|err| format!("Failed to write {}: {}", path, err))?;
+        files_changed += 1;
+    }
+
+    if args.in_place {
+        eprintln!(
+            "Redacted {} secret(s) in {} file(s); originals saved with suffix {}",
+            redactions, files_changed, args.backup_suffix
+        );
+    } else {
+        eprintln!("Redacted {} secret(s)", redactions);
+    }
+    Ok(())
+}
+
+fn run_rules_test(args: &RulesTestArgs) -> Result<(), String> {
+    let detectors = match args.config {
+        Some(ref config_path) => detector::load_detectors(Path::new(config_path)),
+        None => detector::initialize_detectors(None, &config::DetectorSelection::default()).map(
+            |configured| {
+                print_warnings(&configured.warnings);
+                configured.detectors
+            },
+        ),
+    }
+    .map_err(|err| err.to_string())?;
+
+    let results = rules::test_detectors(&detectors);
+    let mut failed = 0;
+    let mut skipped = 0;
+
+    for result in &results {
+        if result.skipped() {
+            skipped += 1;
+            println!("SKIP  {} (no examples)", result.name);
+        } else if result.passed() {
+            println!("PASS  {} ({} examples)", result.name, result.examples_run);
+        } else {
+            failed += 1;
+            println!("FAIL  {}", result.name);
+            for failure in &result.failures {
+                let expectation = if failure.expected_match {
+                    "positive example did not match"
+                } else {
+                    "negative example matched"
+                };
+                println!("      {expectation}: {:?}", failure.example);
+            }
+        }
+    }
+
+    println!(
+        "\n{} detector(s): {} passed, {} failed, {} skipped",
+        results.len(),
+        results.len() - failed - skipped,
+        failed,
+        skipped
+    );
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_rules_lint(args: &RulesLintArgs) -> Result<(), String> {
+    let builtin_names: Vec<String> = detector::default_detectors()
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|detector| detector.name)
+        .collect();
+
+    let (issues, source) = match args.config {
+        Some(ref config_path) => (
+            rules::lint_config_file(Path::new(config_path), &builtin_names)?,
+            config_path.clone(),
+        ),
+        None => {
+            let mut issues = rules::lint_detectors_toml(detector::DEFAULT_DETECTORS)
+                .map_err(|err| format!("Failed to parse built-in detectors: {}", err))?;
+            let mut sources = vec!["built-in detectors".to_string()];
+            let mut inherited = builtin_names;
+            for config_source in config::discover_sources() {
+                for mut issue in rules::lint_config_file(&config_source.path, &inherited)? {
+                    issue.detector = format!("{} ({})", issue.detector, config_source.label());
+                    issues.push(issue);
+                }
+                if let Ok(set) = config::resolve_file(&config_source.path) {
+                    inherited.extend(set.into_detectors().into_iter().map(|d| d.name));
+                }
+                sources.push(config_source.label());
+            }
+            (issues, sources.join(", "))
+        }
+    };
+
+    for issue in &issues {
+        println!(
+            "{:<8} {}: {}",
+            issue.level.as_str(),
+            issue.detector,
+            issue.message
+        );
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.level == rules::LintLevel::Error)
+        .count();
+    let warnings = issues.len() - errors;
+
+    if issues.is_empty() {
+        println!("No issues found in {source}");
+    } else {
+        println!(
+            "\n{} error(s), {} warning(s) in {}",
+            errors, warnings, source
+        );
+    }
+
+    if errors > 0 || (args.deny_warnings && warnings > 0) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_rules_list(args: &RulesListArgs) -> Result<(), String> {
+    let detector_set = config::resolve_detectors(&config::discover_sources())?;
+
+    for active in &detector_set.active {
+        let detector = &active.detector;
+        println!(
+            "{:<8} {:<32} {:<8} {}",
+            detector.metadata.id.as_deref().unwrap_or("-"),
+            detector.name,
+            detector.severity.as_str(),
+            detector.finding_type
+        );
+        if args.explain {
+            println!("    defined in {}", active.origin.defined_in);
+            for layer in &active.origin.overridden_in {
+                println!("    overridden in {layer}");
+            }
+        }
+    }
+
+    if args.explain {
+        for disabled in &detector_set.disabled {
+            println!(
+                "{:<8} {:<32} disabled in {}",
+                "-", disabled.name, disabled.disabled_in
+            );
+        }
+        for (name, profile) in &detector_set.profiles {
+            println!("profile {name}: {}", describe_selection(profile));
+        }
+        for warning in &detector_set.warnings {
+            println!("WARNING: {warning}");
+        }
+    }
+
+    println!(
+        "\n{} active detector(s), {} disabled",
+        detector_set.active.len(),
+        detector_set.disabled.len()
+    );
+    Ok(())
+}
+
+fn describe_selection(selection: &config::DetectorSelection) -> String {
+    let filters: Vec<String> = [
+        ("only_tags", &selection.only_tags),
+        ("skip_tags", &selection.skip_tags),
+        ("only_rules", &selection.only_rules),
+        ("skip_rules", &selection.skip_rules),
+    ]
+    .into_iter()
+    .filter(|(_, values)| !values.is_empty())
+    .map(|(key, values)| format!("{key}={}", values.join(",")))
+    .collect();
+
+    if filters.is_empty() {
+        "every detector".to_string()
+    } else {
+        filters.join(" ")
+    }
+}
+
+fn run_rules_show(args: &RulesShowArgs) -> Result<(), String> {
+    let detector_set = config::resolve_detectors(&config::discover_sources())?;
+    let active = detector_set
+        .active
+        .iter()
+        .find(|active| {
+            active
+                .detector
+                .metadata
+                .id
+                .as_deref()
+                .is_some_and(|id| id.eq_ignore_ascii_case(&args.id))
+        })
+        .or_else(|| {
+            detector_set
+                .active
+                .iter()
+                .find(|active| active.detector.name == args.id)
+        })
+        .ok_or_else(|| format!("No active detector with id or name '{}'", args.id))?;
+
+    let detector = &active.detector;
+    let metadata = &detector.metadata;
+    println!(
+        "{} {}",
+        metadata.id.as_deref().unwrap_or("-"),
+        detector.name
+    );
+    println!("Finding type:  {}", detector.finding_type);
+    println!("Severity:      {}", detector.severity);
+    if !metadata.tags.is_empty() {
+        println!("Tags:          {}", metadata.tags.join(", "));
+    }
+    println!("Pattern:       {}", detector.regex.as_str());
+    if !detector.paths.is_empty() {
+        println!("Paths:         {}", join_patterns(&detector.paths));
+    }
+    if !detector.exclude_paths.is_empty() {
+        println!("Exclude paths: {}", join_patterns(&detector.exclude_paths));
+    }
+    println!("Defined in:    {}", active.origin.defined_in);
+    if let Some(ref description) = metadata.description {
+        println!("\nDescription:\n  {description}");
+    }
+    if let Some(ref remediation) = metadata.remediation {
+        println!("\nRemediation:\n  {remediation}");
+    }
+    if !metadata.references.is_empty() {
+        println!("\nReferences:");
+        for reference in &metadata.references {
+            println!("  - {reference}");
+        }
+    }
+    Ok(())
+}
+
+fn join_patterns(patterns: &[glob::Pattern]) -> String {
+    patterns
+        .iter()
+        .map(glob::Pattern::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn run_rules_import(args: &RulesImportArgs) -> Result<(), String> {
+    let result = match args.from {
+        RuleFormat::Gitleaks => import::import_gitleaks_file(Path::new(&args.file), args.severity)?,
+    };
+
+    for warning in &result.warnings {
+        eprintln!("WARNING  {}: {}", warning.rule, warning.message);
+    }
+
+    match args.output {
+        Some(ref output_path) => utils::write_to_file(output_path, &result.detectors_toml)
+            .map_err(|err| format!("Failed to write detectors to '{}': {}", output_path, err))?,
+        None => print!("{}", result.detectors_toml),
+    }
+
+    eprintln!(
+        "Imported {} rule(s), skipped {}, {} warning(s)",
+        result.imported,
+        result.skipped,
+        result.warnings.len()
+    );
+    Ok(())
+}
+
+/// resolve_config_path accepts a detectors.toml path or the directory containing one.
+fn resolve_config_path(path: &str) -> Result<PathBuf, String> {
+    let path = Path::new(path);
+    let config_path = if path.is_dir() {
+        path.join("detectors.toml")
+    } else {
+        path.to_path_buf()
+    };
+
+    if !config_path.is_file() {
+        return Err(format!(
+            "No detectors.toml found at {}",
+            config_path.display()
+        ));
+    }
+    Ok(config_path)
+}
+
+fn trust_config(args: &ConfigTrustArgs) -> Result<(), String> {
+    let config_path = resolve_config_path(&args.path)?;
+    let mut store = trust::TrustStore::load()?;
+
+    let (record, added) = if args.hash {
+        let digest = trust::config_digest(&config_path)?;
+        (digest.clone(), store.trust_hash(digest))
+    } else {
+        let directory = trust::config_directory(&config_path)
+            .ok_or_else(|| format!("Failed to resolve directory of {}", config_path.display()))?;
+        (directory.clone(), store.trust_directory(directory))
+    };
+
+    if !added {
+        println!("Already trusted: {record}");
+        return Ok(());
+    }
+
+    let store_path = store.save()?;
+    println!("Trusted {} ({})", config_path.display(), record);
+    println!("Trust records: {}", store_path.display());
+    Ok(())
+}
+
+fn untrust_config(args: &ConfigUntrustArgs) -> Result<(), String> {
+    let config_path = resolve_config_path(&args.path)?;
+    let mut store = trust::TrustStore::load()?;
+
+    if !store.untrust(&config_path) {
+        println!("{} was not trusted", config_path.display());
+        return Ok(());
+    }
+
+    store.save()?;
+    println!("Removed trust for {}", config_path.display());
+    Ok(())
+}
+
 fn install_hook(args: &HookInstallArgs) -> Result<(), String> {
     let hook_content = match args.hook_type {
         HookType::PrePush => hooks::generate_pre_push_hook(args),
@@ -449,32 +1161,33 @@ fn verify_binary_integrity() -> Result<(), String> {
     Ok(())
 }
 
-fn calculate_exit_code(findings: &[Finding], exit_mode: &ExitMode) -> i32 {
-    if findings.is_empty() {
+fn calculate_exit_code(findings: &[Finding], policy: &FailurePolicy) -> i32 {
+    let severity_counts = report::get_severity_counts(findings);
+    if !policy.is_violated_by(&severity_counts) {
         return 0;
     }
 
-    match exit_mode {
-        ExitMode::Always => 0,
-        ExitMode::Critical => {
-            let has_high = findings
-                .iter()
-                .any(|finding| finding.severity == Severity::High);
-            if has_high { 1 } else { 0 }
-        }
-        ExitMode::Strict => 1,
+    if !policy.severity_exit_codes {
+        return EXIT_CODE_FINDINGS;
     }
+
+    findings
+        .iter()
+        .map(|finding| finding.severity)
+        .max()
+        .map_or(EXIT_CODE_FINDINGS, policy::severity_exit_code)
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        Severity, calculate_exit_code, ensure_global_hook_target_is_safe,
+        calculate_exit_code, ensure_global_hook_target_is_safe,
         ensure_local_hook_target_is_safe_to_create, managed_global_hooks_dir,
         resolve_hook_uninstall_target, resolve_local_hooks_dir_from,
     };
     use crate::cli::ExitMode;
-    use crate::report::Finding;
+    use crate::policy::FailurePolicy;
+    use crate::report::{DetectorMetadata, Finding, Severity};
     use std::env;
     use std::fs;
     use std::process::Command;
@@ -488,6 +1201,10 @@ mod tests {
         std::env::temp_dir().join(format!("keywatch_{name}_{timestamp}"))
     }
 
+    fn policy(exit_mode: ExitMode) -> FailurePolicy {
+        FailurePolicy::from_exit_mode(&exit_mode)
+    }
+
     fn init_git_repo(path: &std::path::Path) {
         fs::create_dir_all(path).expect("create repo dir");
         let status = Command::new("git")
@@ -645,33 +1362,151 @@ mod tests {
         let high = Finding {
             file_path: "high.txt".to_string(),
             line_number: 1,
+            byte_offset: None,
             finding_type: "High".to_string(),
             severity: Severity::High,
             matched_content: "secret".to_string(),
             plugin_name: "DetectorHigh".to_string(),
+            metadata: DetectorMetadata::default(),
         };
         let low = Finding {
             file_path: "low.txt".to_string(),
             line_number: 1,
+            byte_offset: None,
             finding_type: "Low".to_string(),
             severity: Severity::Low,
             matched_content: "token".to_string(),
             plugin_name: "DetectorLow".to_string(),
+            metadata: DetectorMetadata::default(),
+        };
+
+        assert_eq!(calculate_exit_code(&[], &policy(ExitMode::Strict)), 0);
+        assert_eq!(
+            calculate_exit_code(std::slice::from_ref(&low), &policy(ExitMode::Always)),
+            0
+        );
+        assert_eq!(
+            calculate_exit_code(std::slice::from_ref(&low), &policy(ExitMode::Critical)),
+            0
+        );
+        assert_eq!(
+            calculate_exit_code(std::slice::from_ref(&high), &policy(ExitMode::Critical)),
+            1
+        );
+        assert_eq!(
+            calculate_exit_code(&[low, high], &policy(ExitMode::Strict)),
+            1
+        );
+    }
+
+    #[test]
+    fn test_calculate_exit_code_critical_mode_counts_critical_not_info() {
+        let critical = Finding {
+            file_path: "prod.env".to_string(),
+            line_number: 1,
+            byte_offset: None,
+            finding_type: "Live Key".to_string(),
+            severity: Severity::Critical,
+            matched_content: "secret".to_string(),
+            plugin_name: "DetectorCritical".to_string(),
+            metadata: DetectorMetadata::default(),
+        };
+        let info = Finding {
+            file_path: "notes.md".to_string(),
+            line_number: 1,
+            byte_offset: None,
+            finding_type: "Private IP".to_string(),
+            severity: Severity::Info,
+            matched_content: "10.0.0.1".to_string(),
+            plugin_name: "DetectorInfo".to_string(),
+            metadata: DetectorMetadata::default(),
         };
 
-        assert_eq!(calculate_exit_code(&[], &ExitMode::Strict), 0);
         assert_eq!(
-            calculate_exit_code(std::slice::from_ref(&low), &ExitMode::Always),
+            calculate_exit_code(std::slice::from_ref(&critical), &policy(ExitMode::Critical)),
+            1
+        );
+        assert_eq!(
+            calculate_exit_code(std::slice::from_ref(&info), &policy(ExitMode::Critical)),
             0
         );
+        assert_eq!(calculate_exit_code(&[info], &policy(ExitMode::Strict)), 1);
+    }
+
+    fn finding_with_severity(severity: Severity) -> Finding {
+        Finding {
+            file_path: "config.env".to_string(),
+            line_number: 1,
+            byte_offset: None,
+            finding_type: severity.as_str().to_string(),
+            severity,
+            matched_content: "value".to_string(),
+            plugin_name: "Detector".to_string(),
+            metadata: DetectorMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn test_calculate_exit_code_with_threshold_and_budgets() {
+        let policy = FailurePolicy {
+            threshold: Some(Severity::Medium),
+            budgets: [(Severity::Low, 5)].into_iter().collect(),
+            severity_exit_codes: false,
+        };
+        let lows = |count: usize| vec![finding_with_severity(Severity::Low); count];
+
+        assert_eq!(calculate_exit_code(&lows(5), &policy), 0);
+        assert_eq!(calculate_exit_code(&lows(6), &policy), 1);
         assert_eq!(
-            calculate_exit_code(std::slice::from_ref(&low), &ExitMode::Critical),
+            calculate_exit_code(&[finding_with_severity(Severity::Info)], &policy),
             0
         );
         assert_eq!(
-            calculate_exit_code(std::slice::from_ref(&high), &ExitMode::Critical),
+            calculate_exit_code(&[finding_with_severity(Severity::Medium)], &policy),
             1
         );
-        assert_eq!(calculate_exit_code(&[low, high], &ExitMode::Strict), 1);
+
+        let tolerant = FailurePolicy {
+            threshold: Some(Severity::Medium),
+            budgets: [(Severity::Medium, 2)].into_iter().collect(),
+            severity_exit_codes: false,
+        };
+        let mediums = vec![finding_with_severity(Severity::Medium); 2];
+        assert_eq!(calculate_exit_code(&mediums, &tolerant), 0);
+    }
+
+    #[test]
+    fn test_calculate_exit_code_reports_highest_severity_when_enabled() {
+        let policy = FailurePolicy {
+            threshold: Some(Severity::Low),
+            budgets: Default::default(),
+            severity_exit_codes: true,
+        };
+
+        assert_eq!(calculate_exit_code(&[], &policy), 0);
+        assert_eq!(
+            calculate_exit_code(&[finding_with_severity(Severity::Info)], &policy),
+            0
+        );
+        assert_eq!(
+            calculate_exit_code(
+                &[
+                    finding_with_severity(Severity::Low),
+                    finding_with_severity(Severity::Info)
+                ],
+                &policy
+            ),
+            11
+        );
+        assert_eq!(
+            calculate_exit_code(
+                &[
+                    finding_with_severity(Severity::Medium),
+                    finding_with_severity(Severity::Critical)
+                ],
+                &policy
+            ),
+            14
+        );
     }
 }
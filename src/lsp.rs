@@ -0,0 +1,543 @@
+use crate::detector::ScanContext;
+use crate::report::Severity;
+use crate::scanner::Scanner;
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{CodeActionRequest, Request as _};
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, CreateFile, CreateFileOptions, Diagnostic, DiagnosticSeverity,
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DocumentChangeOperation, DocumentChanges, NumberOrString, OneOf,
+    OptionalVersionedTextDocumentIdentifier, Position, PublishDiagnosticsParams, Range, ResourceOp,
+    ServerCapabilities, TextDocumentContentChangeEvent, TextDocumentEdit,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url, WorkspaceEdit,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Shown as the source of every diagnostic, and used to recognise our own diagnostics in
+/// code action requests.
+pub const DIAGNOSTIC_SOURCE: &str = "key-watch";
+
+/// Carried in `Diagnostic.data` so code actions know what was found without rescanning.
+#[derive(Serialize, Deserialize)]
+struct FindingData {
+    finding_type: String,
+}
+
+struct Document {
+    version: i32,
+    text: String,
+}
+
+/// run_stdio serves LSP over stdin and stdout until the client shuts the server down.
+pub fn run_stdio(scanner: Scanner) -> Result<(), String> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection, &scanner)?;
+    drop(connection);
+    io_threads
+        .join()
+        .map_err(|err| format!("LSP I/O failed: {}", err))
+}
+
+/// serve runs the LSP handshake and message loop on `connection`. Open documents are
+/// synced incrementally and rescanned on every change; diagnostics are published for the
+/// whole document so stale ones disappear.
+pub fn serve(connection: &Connection, scanner: &Scanner) -> Result<(), String> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(
+            TextDocumentSyncKind::INCREMENTAL,
+        )),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        ..ServerCapabilities::default()
+    };
+    let capabilities = serde_json::to_value(capabilities)
+        .map_err(|err| format!("Failed to serialize capabilities: {}", err))?;
+    let params = connection
+        .initialize(capabilities)
+        .map_err(|err| format!("LSP initialization failed: {}", err))?;
+    let workspace = workspace_roots(&params);
+
+    let mut documents: HashMap<Url, Document> = HashMap::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection
+                    .handle_shutdown(&request)
+                    .map_err(|err| format!("LSP shutdown failed: {}", err))?
+                {
+                    return Ok(());
+                }
+                let response = handle_request(request, &documents);
+                send(connection, Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                if let Some(uri) = handle_notification(notification, &mut documents) {
+                    publish(connection, scanner, &workspace, &uri, documents.get(&uri))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn send(connection: &Connection, message: Message) -> Result<(), String> {
+    connection
+        .sender
+        .send(message)
+        .map_err(|err| format!("LSP client disconnected: {}", err))
+}
+
+/// handle_notification updates the open documents and returns the one to rescan.
+fn handle_notification(
+    notification: Notification,
+    documents: &mut HashMap<Url, Document>,
+) -> Option<Url> {
+    match notification.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: DidOpenTextDocumentParams =
+                notification.extract(DidOpenTextDocument::METHOD).ok()?;
+            let document = params.text_document;
+            documents.insert(
+                document.uri.clone(),
+                Document {
+                    version: document.version,
+                    text: document.text,
+                },
+            );
+            Some(document.uri)
+        }
+        DidChangeTextDocument::METHOD => {
+            let params: DidChangeTextDocumentParams =
+                notification.extract(DidChangeTextDocument::METHOD).ok()?;
+            let uri = params.text_document.uri;
+            let document = documents.get_mut(&uri)?;
+            for change in params.content_changes {
+                apply_change(&mut document.text, change);
+            }
+            document.version = params.text_document.version;
+            Some(uri)
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: DidCloseTextDocumentParams =
+                notification.extract(DidCloseTextDocument::METHOD).ok()?;
+            documents.remove(&params.text_document.uri);
+            Some(params.text_document.uri)
+        }
+        _ => None,
+    }
+}
+
+fn handle_request(request: Request, documents: &HashMap<Url, Document>) -> Response {
+    if request.method != CodeActionRequest::METHOD {
+        return Response::new_err(
+            request.id,
+            lsp_server::ErrorCode::MethodNotFound as i32,
+            format!("Unsupported request {}", request.method),
+        );
+    }
+    let id = request.id.clone();
+    match request.extract::<CodeActionParams>(CodeActionRequest::METHOD) {
+        Ok((_, params)) => {
+            let actions = documents
+                .get(&params.text_document.uri)
+                .map(|document| code_actions(&params, document))
+                .unwrap_or_default();
+            Response::new_ok(id, actions)
+        }
+        Err(err) => Response::new_err(
+            id,
+            lsp_server::ErrorCode::InvalidParams as i32,
+            format!("{:?}", err),
+        ),
+    }
+}
+
+fn publish(
+    connection: &Connection,
+    scanner: &Scanner,
+    workspace: &[PathBuf],
+    uri: &Url,
+    document: Option<&Document>,
+) -> Result<(), String> {
+    let (diagnostics, version) = match document {
+        Some(document) => {
+            let name = document_name(uri);
+            let roots = document_roots(Path::new(&name), workspace);
+            (
+                diagnostics(scanner, &name, &roots, &document.text),
+                Some(document.version),
+            )
+        }
+        // Closed documents have their diagnostics cleared.
+        None => (Vec::new(), None),
+    };
+    let params = PublishDiagnosticsParams {
+        uri: uri.clone(),
+        diagnostics,
+        version,
+    };
+    send(
+        connection,
+        Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )),
+    )
+}
+
+/// workspace_roots lists the folders the client opened, from its initialize params.
+fn workspace_roots(params: &serde_json::Value) -> Vec<PathBuf> {
+    let folders = params["workspaceFolders"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|folder| folder["uri"].as_str());
+    folders
+        .chain(params["rootUri"].as_str())
+        .filter_map(|uri| Url::parse(uri).ok()?.to_file_path().ok())
+        .collect()
+}
+
+/// document_roots returns the workspace folders containing `path`, in the form
+/// `scan_paths` passes to detector path globs. Outside every workspace folder the
+/// document's project root is used.
+fn document_roots(path: &Path, workspace: &[PathBuf]) -> Vec<Option<String>> {
+    let roots: Vec<Option<String>> = workspace
+        .iter()
+        .filter(|root| path.starts_with(root))
+        .map(|root| Some(root.to_string_lossy().into_owned()))
+        .collect();
+    if roots.is_empty() {
+        vec![Some(project_root(path).to_string_lossy().into_owned())]
+    } else {
+        roots
+    }
+}
+
+/// document_name is the path path-scoped detectors see for a document.
+fn document_name(uri: &Url) -> String {
+    uri.to_file_path()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| uri.path().to_string())
+}
+
+/// diagnostics runs the scanner's detectors over `text` and returns one diagnostic per
+/// occurrence of a secret, spanning exactly the secret: for key=value detectors, only the
+/// value. `name`, found under `roots`, drives path-scoped detectors.
+pub fn diagnostics(
+    scanner: &Scanner,
+    name: &str,
+    roots: &[Option<String>],
+    text: &str,
+) -> Vec<Diagnostic> {
+    let ctx = ScanContext {
+        path: name,
+        content: text,
+    };
+    let line_starts = line_starts(text);
+    let mut diagnostics = Vec::new();
+
+    for detector in scanner
+        .detectors()
+        .iter()
+        .filter(|detector| detector.applies_to(name, roots))
+    {
+        // Where to continue searching for repeated matches of the same text on a line.
+        let mut resume: HashMap<(usize, &str), usize> = HashMap::new();
+        let matches = detector.find_all(&ctx);
+        for found in &matches {
+            let line_start = line_starts
+                .get(found.line_number.saturating_sub(1))
+                .copied()
+                .unwrap_or(0);
+            let from = resume
+                .get(&(found.line_number, found.matched_content.as_str()))
+                .copied()
+                .unwrap_or(line_start);
+            let Some(offset) = text[from..].find(&found.matched_content) else {
+                continue;
+            };
+            let match_start = from + offset;
+            let match_end = match_start + found.matched_content.len();
+            resume.insert(
+                (found.line_number, found.matched_content.as_str()),
+                match_end,
+            );
+            // A range that is not within the match falls back to the whole match.
+            let secret = Some(detector.secret_range(&found.matched_content))
+                .filter(|range| found.matched_content.get(range.clone()).is_some())
+                .unwrap_or(0..found.matched_content.len());
+            let (start, end) = (match_start + secret.start, match_start + secret.end);
+
+            let code = detector
+                .metadata()
+                .id
+                .clone()
+                .unwrap_or_else(|| detector.name().to_string());
+            diagnostics.push(Diagnostic {
+                range: Range::new(
+                    position_at(text, &line_starts, start),
+                    position_at(text, &line_starts, end),
+                ),
+                severity: Some(lsp_severity(detector.severity())),
+                code: Some(NumberOrString::String(code)),
+                source: Some(DIAGNOSTIC_SOURCE.to_string()),
+                message: format!(
+                    "{} detected by {}",
+                    detector.finding_type(),
+                    detector.name()
+                ),
+                data: serde_json::to_value(FindingData {
+                    finding_type: detector.finding_type().to_string(),
+                })
+                .ok(),
+                ..Diagnostic::default()
+            });
+        }
+    }
+
+    diagnostics.sort_by_key(|diagnostic| {
+        (
+            diagnostic.range.start.line,
+            diagnostic.range.start.character,
+        )
+    });
+    diagnostics
+}
+
+/// lsp_severity maps KeyWatch severities onto the four LSP levels.
+pub fn lsp_severity(severity: Severity) -> DiagnosticSeverity {
+    match severity {
+        Severity::Critical | Severity::High => DiagnosticSeverity::ERROR,
+        Severity::Medium => DiagnosticSeverity::WARNING,
+        Severity::Low => DiagnosticSeverity::INFORMATION,
+        Severity::Info => DiagnosticSeverity::HINT,
+    }
+}
+
+/// code_actions offers, for each KeyWatch diagnostic in the request, to replace the secret
+/// with an environment variable reference, or to do that and move the value into a
+/// git-ignored `.env` file next to the repository root.
+fn code_actions(params: &CodeActionParams, document: &Document) -> Vec<CodeActionOrCommand> {
+    let uri = &params.text_document.uri;
+    let path = uri.to_file_path().ok();
+    let line_starts = line_starts(&document.text);
+    let mut actions = Vec::new();
+
+    for diagnostic in &params.context.diagnostics {
+        if diagnostic.source.as_deref() != Some(DIAGNOSTIC_SOURCE) {
+            continue;
+        }
+        let Some(finding_type) = diagnostic
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<FindingData>(data).ok())
+            .map(|data| data.finding_type)
+        else {
+            continue;
+        };
+        let (Some(start), Some(end)) = (
+            byte_offset(&document.text, &line_starts, diagnostic.range.start),
+            byte_offset(&document.text, &line_starts, diagnostic.range.end),
+        ) else {
+            continue;
+        };
+
+        let variable = env_var_name(&finding_type);
+        let secret = &document.text[start..end];
+        let extension = path
+            .as_deref()
+            .and_then(Path::extension)
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        let (edit_start, edit_end, reference) =
+            env_reference(&document.text, start, end, extension, &variable);
+        let replace = TextEdit::new(
+            Range::new(
+                position_at(&document.text, &line_starts, edit_start),
+                position_at(&document.text, &line_starts, edit_end),
+            ),
+            reference,
+        );
+
+        let mut changes = HashMap::new();
+        changes.insert(uri.clone(), vec![replace.clone()]);
+        actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+            title: format!("Replace with environment variable {variable}"),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![diagnostic.clone()]),
+            edit: Some(WorkspaceEdit {
+                changes: Some(changes),
+                ..WorkspaceEdit::default()
+            }),
+            ..CodeAction::default()
+        }));
+
+        if let Some(path) = path.as_deref() {
+            let root = project_root(path);
+            let env_line = format!("{variable}={secret}\n");
+            let mut operations = vec![DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version: Some(document.version),
+                },
+                edits: vec![OneOf::Left(replace)],
+            })];
+            operations.extend(append_to_file(&root.join(".env"), &env_line));
+            if !gitignore_covers_env(&root) {
+                operations.extend(append_to_file(&root.join(".gitignore"), ".env\n"));
+            }
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Move to .env as {variable}"),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(WorkspaceEdit {
+                    document_changes: Some(DocumentChanges::Operations(operations)),
+                    ..WorkspaceEdit::default()
+                }),
+                ..CodeAction::default()
+            }));
+        }
+    }
+    actions
+}
+
+/// env_var_name turns a finding type like `AWS Access Key` into `AWS_ACCESS_KEY`.
+fn env_var_name(finding_type: &str) -> String {
+    finding_type
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(str::to_ascii_uppercase)
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// env_reference returns the span to replace and how the language of `extension` reads an
+/// environment variable. In code, surrounding quotes are replaced too, since the
+/// reference is an expression rather than a string.
+fn env_reference(
+    text: &str,
+    start: usize,
+    end: usize,
+    extension: &str,
+    variable: &str,
+) -> (usize, usize, String) {
+    let expression = match extension {
+        "py" => format!("os.environ[\"{variable}\"]"),
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" => format!("process.env.{variable}"),
+        "go" => format!("os.Getenv(\"{variable}\")"),
+        "rb" => format!("ENV[\"{variable}\"]"),
+        _ => return (start, end, format!("${{{variable}}}")),
+    };
+
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+    match (before, after) {
+        (Some(open), Some(close)) if open == close && matches!(open, '"' | '\'' | '`') => {
+            (start - 1, end + 1, expression)
+        }
+        _ => (start, end, expression),
+    }
+}
+
+/// project_root is the closest directory above `path` containing `.git`, or the
+/// document's own directory outside a repository.
+fn project_root(path: &Path) -> PathBuf {
+    let dir = path.parent().unwrap_or(path);
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+fn gitignore_covers_env(root: &Path) -> bool {
+    fs::read_to_string(root.join(".gitignore")).is_ok_and(|contents| {
+        contents
+            .lines()
+            .map(str::trim)
+            .any(|line| matches!(line, ".env" | "/.env" | ".env*" | "*.env"))
+    })
+}
+
+/// append_to_file creates `path` if needed and appends `line` to whatever it holds on disk.
+fn append_to_file(path: &Path, line: &str) -> Vec<DocumentChangeOperation> {
+    let Ok(uri) = Url::from_file_path(path) else {
+        return Vec::new();
+    };
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let starts = line_starts(&existing);
+    let end = position_at(&existing, &starts, existing.len());
+    let text = if existing.is_empty() || existing.ends_with('\n') {
+        line.to_string()
+    } else {
+        format!("\n{line}")
+    };
+
+    vec![
+        DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+            uri: uri.clone(),
+            options: Some(CreateFileOptions {
+                overwrite: Some(false),
+                ignore_if_exists: Some(true),
+            }),
+            annotation_id: None,
+        })),
+        DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+            edits: vec![OneOf::Left(TextEdit::new(Range::new(end, end), text))],
+        }),
+    ]
+}
+
+/// apply_change applies one didChange event; a change without a range replaces the text.
+fn apply_change(text: &mut String, change: TextDocumentContentChangeEvent) {
+    let Some(range) = change.range else {
+        *text = change.text;
+        return;
+    };
+    let starts = line_starts(text);
+    let start = byte_offset(text, &starts, range.start).unwrap_or(text.len());
+    let end = byte_offset(text, &starts, range.end)
+        .unwrap_or(text.len())
+        .max(start);
+    text.replace_range(start..end, &change.text);
+}
+
+fn line_starts(text: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+        .collect()
+}
+
+/// position_at converts a byte offset into an LSP position, whose character is counted in
+/// UTF-16 code units.
+fn position_at(text: &str, line_starts: &[usize], offset: usize) -> Position {
+    let line = line_starts.partition_point(|&start| start <= offset) - 1;
+    let character = text[line_starts[line]..offset].encode_utf16().count();
+    Position::new(line as u32, character as u32)
+}
+
+/// byte_offset converts an LSP position back into a byte offset, clamping the character to
+/// the end of the line as the protocol requires.
+fn byte_offset(text: &str, line_starts: &[usize], position: Position) -> Option<usize> {
+    let line_start = *line_starts.get(position.line as usize)?;
+    let line_end = line_starts
+        .get(position.line as usize + 1)
+        .map_or(text.len(), |next| next - 1);
+    let mut units = 0;
+    for (index, c) in text[line_start..line_end].char_indices() {
+        if units >= position.character as usize {
+            return Some(line_start + index);
+        }
+        units += c.len_utf16();
+    }
+    Some(line_end)
+}
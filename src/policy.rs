@@ -0,0 +1,80 @@
+use crate::cli::ExitMode;
+use crate::report::{Severity, SeverityCounts};
+use std::collections::BTreeMap;
+
+/// Exit code used when findings fail the scan.
+pub const EXIT_CODE_FINDINGS: i32 = 1;
+
+/// Decides whether a set of findings fails the scan.
+///
+/// Every severity has a budget: the number of findings allowed before the scan fails.
+/// Severities at or above `threshold` default to a budget of zero, everything below it
+/// is unlimited, and explicit `budgets` override both.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FailurePolicy {
+    pub threshold: Option<Severity>,
+    pub budgets: BTreeMap<Severity, usize>,
+    pub severity_exit_codes: bool,
+}
+
+impl FailurePolicy {
+    pub fn from_exit_mode(exit_mode: &ExitMode) -> Self {
+        let threshold = match exit_mode {
+            ExitMode::Always => None,
+            ExitMode::Critical => Some(Severity::High),
+            ExitMode::Strict => Some(Severity::Info),
+        };
+
+        Self {
+            threshold,
+            ..Self::default()
+        }
+    }
+
+    /// budget returns how many findings of `severity` are allowed, or None if unlimited.
+    pub fn budget(&self, severity: Severity) -> Option<usize> {
+        match self.budgets.get(&severity) {
+            Some(budget) => Some(*budget),
+            None if self
+                .threshold
+                .is_some_and(|threshold| severity >= threshold) =>
+            {
+                Some(0)
+            }
+            None => None,
+        }
+    }
+
+    /// is_violated_by reports whether any severity exceeds its budget.
+    pub fn is_violated_by(&self, counts: &SeverityCounts) -> bool {
+        Severity::ALL.into_iter().any(|severity| {
+            self.budget(severity)
+                .is_some_and(|budget| counts.get(severity) > budget)
+        })
+    }
+}
+
+/// severity_exit_code is the exit code reported by `--severity-exit-codes` when the
+/// highest severity among the findings is `severity`.
+pub fn severity_exit_code(severity: Severity) -> i32 {
+    match severity {
+        Severity::Info => 10,
+        Severity::Low => 11,
+        Severity::Medium => 12,
+        Severity::High => 13,
+        Severity::Critical => 14,
+    }
+}
+
+/// parse_finding_budget parses a `SEVERITY=COUNT` pair such as `LOW=5`.
+pub fn parse_finding_budget(value: &str) -> Result<(Severity, usize), String> {
+    let (severity, count) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected SEVERITY=COUNT, got '{value}'"))?;
+    let severity = severity.trim().parse::<Severity>()?;
+    let count = count
+        .trim()
+        .parse::<usize>()
+        .map_err(|err| format!("invalid finding count '{}': {}", count.trim(), err))?;
+    Ok((severity, count))
+}
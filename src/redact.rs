@@ -0,0 +1,164 @@
+use crate::detector::{Detector, Match, MatchLocator, ScanContext};
+use crate::error::{self, Error};
+use crate::scanner::Scanner;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+/// What a detected secret is replaced with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Replacement {
+    /// `[REDACTED:<finding type>]`
+    Token,
+    /// A fixed string, e.g. `****`.
+    Mask(String),
+}
+
+impl Replacement {
+    fn for_finding(&self, finding_type: &str) -> String {
+        match self {
+            Replacement::Token => format!("[REDACTED:{finding_type}]"),
+            Replacement::Mask(mask) => mask.clone(),
+        }
+    }
+}
+
+/// The redacted content and how many secrets were replaced in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redacted {
+    pub content: String,
+    pub redactions: usize,
+}
+
+/// redact_text replaces every secret the scanner's detectors find in `content`, where
+/// they find it. Only the secret itself is replaced, so the key of a `password = '...'`
+/// assignment stays readable. `name`, found under `roots` as in `Scanner::scan_file`,
+/// drives path-scoped detectors. A secret spanning
+/// several lines is replaced line by line, so the output has the same number of lines as
+/// the input.
+pub fn redact_text(
+    scanner: &Scanner,
+    name: &str,
+    roots: &[Option<String>],
+    content: &str,
+    replacement: &Replacement,
+) -> Redacted {
+    let ctx = ScanContext {
+        path: name,
+        content,
+    };
+    let matches: Vec<(&dyn Detector, Match)> = scanner
+        .detectors()
+        .iter()
+        .filter(|detector| detector.applies_to(name, roots))
+        .flat_map(|detector| {
+            detector
+                .find_all(&ctx)
+                .into_iter()
+                .map(move |found| (detector, found))
+        })
+        .collect();
+
+    let mut locator = MatchLocator::new(content);
+    let mut spans: Vec<(Range<usize>, &str)> = Vec::new();
+    for (detector, found) in &matches {
+        let Some(offset) = locator.locate(detector.name(), found) else {
+            continue;
+        };
+        let secret = detector.secret_range(&found.matched_content);
+        if secret.is_empty() || found.matched_content.get(secret.clone()).is_none() {
+            continue;
+        }
+        spans.push((
+            offset + secret.start..offset + secret.end,
+            detector.finding_type(),
+        ));
+    }
+
+    // Overlapping secrets, such as one found by two detectors, are replaced as one.
+    spans.sort_by_key(|(span, _)| span.start);
+    let mut merged: Vec<(Range<usize>, &str)> = Vec::with_capacity(spans.len());
+    for (span, finding_type) in spans {
+        match merged.last_mut() {
+            Some((last, _)) if span.start < last.end => last.end = last.end.max(span.end),
+            _ => merged.push((span, finding_type)),
+        }
+    }
+
+    let mut redacted = String::with_capacity(content.len());
+    let mut copied = 0;
+    for (span, finding_type) in &merged {
+        redacted.push_str(&content[copied..span.start]);
+        let token = replacement.for_finding(finding_type);
+        let line_count = content[span.clone()].split('\n').count();
+        redacted.push_str(&vec![token.as_str(); line_count].join("\n"));
+        copied = span.end;
+    }
+    redacted.push_str(&content[copied..]);
+
+    Redacted {
+        content: redacted,
+        redactions: merged.len(),
+    }
+}
+
+/// redact_stream copies `reader` to `writer` one line at a time, redacting each line and
+/// flushing it before the next is read. Line endings are kept as they were. Returns the
+/// number of secrets replaced.
+pub fn redact_stream(
+    scanner: &Scanner,
+    name: &str,
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+    replacement: &Replacement,
+) -> error::Result<usize> {
+    let io_error = |source| Error::Io {
+        path: name.into(),
+        source,
+    };
+    let mut line = Vec::new();
+    let mut redactions = 0;
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).map_err(io_error)? == 0 {
+            break;
+        }
+        redactions +=
+            write_redacted(scanner, name, &line, &mut writer, replacement).map_err(io_error)?;
+    }
+
+    Ok(redactions)
+}
+
+/// write_redacted redacts one line, or the part of one that is available so far, and
+/// writes it to `writer` with its line ending unchanged. Only valid UTF-8 is redacted;
+/// other bytes, and text without secrets, are written exactly as they were read. Returns
+/// the number of secrets replaced.
+pub(crate) fn write_redacted(
+    scanner: &Scanner,
+    name: &str,
+    line: &[u8],
+    writer: &mut impl Write,
+    replacement: &Replacement,
+) -> io::Result<usize> {
+    let body_len = line
+        .iter()
+        .rposition(|&byte| byte != b'\n' && byte != b'\r')
+        .map_or(0, |last| last + 1);
+    let (body, ending) = line.split_at(body_len);
+    let mut redactions = 0;
+    for chunk in body.utf8_chunks() {
+        // Streams have no directory; their name is matched as given, as `scan -` does.
+        let redacted = redact_text(scanner, name, &[None], chunk.valid(), replacement);
+        if redacted.redactions > 0 {
+            writer.write_all(redacted.content.as_bytes())?;
+            redactions += redacted.redactions;
+        } else {
+            writer.write_all(chunk.valid().as_bytes())?;
+        }
+        writer.write_all(chunk.invalid())?;
+    }
+    writer.write_all(ending)?;
+    writer.flush()?;
+    Ok(redactions)
+}
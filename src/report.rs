@@ -1,23 +1,83 @@
 use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(Serialize, Clone, PartialEq, Copy)]
+#[derive(Serialize, Clone, PartialEq, Eq, Hash, Copy, Debug)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum Severity {
+    Critical,
     High,
     Medium,
     Low,
+    Info,
 }
 
 impl Severity {
-    pub fn from_string(s: &str) -> Severity {
-        match s.to_uppercase().as_str() {
-            "HIGH" => Severity::High,
-            "MEDIUM" => Severity::Medium,
-            _ => Severity::Low,
+    /// All severities, from most to least severe.
+    pub const ALL: [Severity; 5] = [
+        Severity::Critical,
+        Severity::High,
+        Severity::Medium,
+        Severity::Low,
+        Severity::Info,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Critical => "CRITICAL",
+            Self::High => "HIGH",
+            Self::Medium => "MEDIUM",
+            Self::Low => "LOW",
+            Self::Info => "INFO",
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Critical => 4,
+            Self::High => 3,
+            Self::Medium => 2,
+            Self::Low => 1,
+            Self::Info => 0,
         }
     }
 }
 
+impl FromStr for Severity {
+    type Err = String;
+
+    /// Severity names are matched case-insensitively; anything else is rejected.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|severity| severity.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!("unknown severity '{s}' (expected CRITICAL, HIGH, MEDIUM, LOW or INFO)")
+            })
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Ord for Severity {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank().cmp(&other.rank())
+    }
+}
+
+impl PartialOrd for Severity {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum ScanStatus {
@@ -25,23 +85,160 @@ pub enum ScanStatus {
     Fail,
 }
 
+/// Descriptive detector metadata, copied into every finding the detector reports.
+#[derive(Serialize, Clone, Default, Debug, PartialEq, Eq)]
+pub struct DetectorMetadata {
+    /// Stable rule ID such as `KW0001`; unlike names, IDs are never reused.
+    #[serde(rename = "rule_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remediation: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<String>,
+}
+
 /// Represents a single secret finding.
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct Finding {
     pub file_path: String,
+    /// Line of the secret, starting at 1; 0 for secrets found in binary files.
+    #[serde(skip_serializing_if = "is_zero")]
     pub line_number: usize,
+    /// Byte offset of the secret in a binary file, which has no lines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_offset: Option<usize>,
     pub finding_type: String,
     pub severity: Severity,
     pub matched_content: String,
     pub plugin_name: String,
+    #[serde(flatten)]
+    pub metadata: DetectorMetadata,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
 }
 
 /// Metadata about the scanning performed.
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, Default)]
 pub struct ScanMetadata {
     pub files_scanned: usize,
     pub total_lines: usize,
     pub excluded_files: Vec<String>,
+    /// Files the scan could not read as text, in path order.
+    pub skipped_files: Vec<SkippedFile>,
+    /// Scan cache usage; None when the scan ran without `--cache`.
+    pub cache: Option<CacheStats>,
+    /// Problems that did not stop the scan, such as project config changes ignored because
+    /// the config is untrusted. The CLI prints them on stderr.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Why a file was not scanned.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The file contains NUL bytes and is not UTF-16 or UTF-32 text.
+    Binary,
+    PermissionDenied,
+    NotFound,
+    /// The file is larger than the scanner's maximum file size.
+    TooLarge,
+    /// A symbolic link found while walking a directory; links are not followed.
+    Symlink,
+    /// Reading failed for another reason.
+    Unreadable,
+    /// Extracting the archive, with any archives nested in it, exceeded the total
+    /// decompressed size limit.
+    ArchiveTooLarge,
+    /// An archive entry decompressed to far more than its compressed size, as zip bombs do.
+    CompressionRatio,
+    /// An archive nested deeper than the scanner opens.
+    ArchiveDepth,
+    /// Compressed with a format KeyWatch cannot decompress, such as a zstd image layer.
+    UnsupportedCompression,
+}
+
+impl SkipReason {
+    /// from_io_error classifies a failed read.
+    pub fn from_io_error(err: &std::io::Error) -> SkipReason {
+        match err.kind() {
+            std::io::ErrorKind::NotFound => SkipReason::NotFound,
+            std::io::ErrorKind::PermissionDenied => SkipReason::PermissionDenied,
+            _ => SkipReason::Unreadable,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Binary => "binary",
+            Self::PermissionDenied => "permission denied",
+            Self::NotFound => "not found",
+            Self::TooLarge => "too large",
+            Self::Symlink => "symlink",
+            Self::Unreadable => "unreadable",
+            Self::ArchiveTooLarge => "archive too large",
+            Self::CompressionRatio => "compression ratio",
+            Self::ArchiveDepth => "archive nested too deep",
+            Self::UnsupportedCompression => "unsupported compression",
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A file the scan could not read, and why.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+/// skipped_summary describes skipped files by reason, e.g. `2 binary, 1 not found`.
+pub fn skipped_summary(skipped: &[SkippedFile]) -> String {
+    let mut counts: BTreeMap<SkipReason, usize> = BTreeMap::new();
+    for file in skipped {
+        *counts.entry(file.reason).or_default() += 1;
+    }
+    counts
+        .iter()
+        .map(|(reason, count)| format!("{} {}", count, reason))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// How many scanned files the scan cache answered (hits) or had to scan (misses).
+#[derive(Serialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl CacheStats {
+    /// hit_ratio is the share of files answered from the cache, from 0.0 to 1.0.
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+/// Cache usage as reported in the JSON report.
+#[derive(Serialize)]
+pub struct CacheReport {
+    pub hits: usize,
+    pub misses: usize,
+    pub hit_ratio: f64,
 }
 
 /// The overall report.
@@ -52,9 +249,21 @@ pub struct Report {
     pub files_scanned: usize,
     pub total_lines: usize,
     pub excluded_files: Vec<String>,
+    pub skipped_files: Vec<SkippedFile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheReport>,
     pub scan_time: String,
 }
 
+/// format_scan_time renders a scan duration the way reports show it, e.g. `0.3s`.
+pub fn format_scan_time(elapsed: Duration) -> String {
+    format!(
+        "{}.{:01}s",
+        elapsed.as_secs(),
+        elapsed.subsec_millis() / 100
+    )
+}
+
 /// create_report builds the final JSON report based on findings and metadata.
 pub fn create_report(
     findings: Vec<Finding>,
@@ -72,20 +281,60 @@ pub fn create_report(
         files_scanned: metadata.files_scanned,
         total_lines: metadata.total_lines,
         excluded_files: metadata.excluded_files,
+        skipped_files: metadata.skipped_files,
+        cache: metadata.cache.map(|stats| CacheReport {
+            hits: stats.hits,
+            misses: stats.misses,
+            hit_ratio: (stats.hit_ratio() * 1000.0).round() / 1000.0,
+        }),
         scan_time,
     };
 
     serde_json::to_string_pretty(&report)
 }
 
-pub fn get_severity_counts(findings: &[Finding]) -> (usize, usize, usize) {
-    let mut counts = (0, 0, 0);
+/// Number of findings at each severity level.
+#[derive(Serialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct SeverityCounts {
+    pub critical: usize,
+    pub high: usize,
+    pub medium: usize,
+    pub low: usize,
+    pub info: usize,
+}
+
+impl SeverityCounts {
+    pub fn get(&self, severity: Severity) -> usize {
+        match severity {
+            Severity::Critical => self.critical,
+            Severity::High => self.high,
+            Severity::Medium => self.medium,
+            Severity::Low => self.low,
+            Severity::Info => self.info,
+        }
+    }
+}
+
+impl fmt::Display for SeverityCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = Severity::ALL
+            .iter()
+            .map(|severity| format!("{}: {}", severity, self.get(*severity)))
+            .collect();
+        f.write_str(&parts.join(", "))
+    }
+}
+
+pub fn get_severity_counts(findings: &[Finding]) -> SeverityCounts {
+    let mut counts = SeverityCounts::default();
     for finding in findings {
-        counts = match finding.severity {
-            Severity::High => (counts.0 + 1, counts.1, counts.2),
-            Severity::Medium => (counts.0, counts.1 + 1, counts.2),
-            Severity::Low => (counts.0, counts.1, counts.2 + 1),
-        };
+        match finding.severity {
+            Severity::Critical => counts.critical += 1,
+            Severity::High => counts.high += 1,
+            Severity::Medium => counts.medium += 1,
+            Severity::Low => counts.low += 1,
+            Severity::Info => counts.info += 1,
+        }
     }
     counts
 }
@@ -0,0 +1,461 @@
+use crate::detector::{
+    CONFIG_KEYS, DETECTOR_KEYS, DetectorExamples, PROFILE_KEYS, RegexDetector,
+    compile_path_patterns,
+};
+use crate::report::Severity;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// A single example that did not behave as declared in detectors.toml.
+pub struct ExampleFailure {
+    pub example: String,
+    pub expected_match: bool,
+}
+
+/// Outcome of running one detector against its declared examples.
+pub struct DetectorTestResult {
+    pub name: String,
+    pub examples_run: usize,
+    pub failures: Vec<ExampleFailure>,
+}
+
+impl DetectorTestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    pub fn skipped(&self) -> bool {
+        self.examples_run == 0
+    }
+}
+
+/// test_detector checks that every positive example matches and no negative example does.
+pub fn test_detector(detector: &RegexDetector) -> DetectorTestResult {
+    let positive = detector
+        .examples
+        .positive
+        .iter()
+        .map(|example| (example, true));
+    let negative = detector
+        .examples
+        .negative
+        .iter()
+        .map(|example| (example, false));
+
+    let mut examples_run = 0;
+    let mut failures = Vec::new();
+    for (example, expected_match) in positive.chain(negative) {
+        examples_run += 1;
+        if detector.matches_text(example) != expected_match {
+            failures.push(ExampleFailure {
+                example: example.clone(),
+                expected_match,
+            });
+        }
+    }
+
+    DetectorTestResult {
+        name: detector.name.clone(),
+        examples_run,
+        failures,
+    }
+}
+
+pub fn test_detectors(detectors: &[RegexDetector]) -> Vec<DetectorTestResult> {
+    detectors.iter().map(test_detector).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    Error,
+    Warning,
+}
+
+impl LintLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Error => "ERROR",
+            Self::Warning => "WARNING",
+        }
+    }
+}
+
+/// A problem found while statically checking a detectors.toml file.
+#[derive(Debug)]
+pub struct LintIssue {
+    pub level: LintLevel,
+    pub detector: String,
+    pub message: String,
+}
+
+/// lint_detectors_file reads and lints a detectors.toml file without loading it.
+pub fn lint_detectors_file(path: &Path) -> Result<Vec<LintIssue>, String> {
+    lint_config_file(path, &[])
+}
+
+/// lint_config_file lints one configuration layer. Entries named after an `inherited`
+/// detector are partial overrides and only need the fields they change.
+pub fn lint_config_file(path: &Path, inherited: &[String]) -> Result<Vec<LintIssue>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    lint_config_toml(&contents, inherited)
+        .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))
+}
+
+/// lint_detectors_toml reports broken, overbroad and duplicated detector definitions.
+/// Only TOML syntax errors fail outright; everything else becomes a LintIssue.
+pub fn lint_detectors_toml(contents: &str) -> Result<Vec<LintIssue>, toml::de::Error> {
+    lint_config_toml(contents, &[])
+}
+
+/// lint_config_toml is lint_detectors_toml for a layer that builds on `inherited` detectors.
+pub fn lint_config_toml(
+    contents: &str,
+    inherited: &[String],
+) -> Result<Vec<LintIssue>, toml::de::Error> {
+    let document: toml::Table = toml::from_str(contents)?;
+    let mut issues = Vec::new();
+
+    for key in document
+        .keys()
+        .filter(|key| !CONFIG_KEYS.contains(&key.as_str()))
+    {
+        issues.push(LintIssue {
+            level: LintLevel::Warning,
+            detector: "<file>".to_string(),
+            message: format!("unknown top-level key `{key}` is ignored"),
+        });
+    }
+
+    for key in ["extends", "disable"] {
+        if document
+            .get(key)
+            .is_some_and(|value| value.clone().try_into::<Vec<String>>().is_err())
+        {
+            issues.push(LintIssue {
+                level: LintLevel::Error,
+                detector: "<file>".to_string(),
+                message: format!("`{key}` must be a list of strings"),
+            });
+        }
+    }
+
+    match document.get("profiles") {
+        Some(toml::Value::Table(profiles)) => lint_profiles(profiles, &mut issues),
+        Some(_) => issues.push(LintIssue {
+            level: LintLevel::Error,
+            detector: "<file>".to_string(),
+            message: "`profiles` must be a table of [profiles.<name>] tables".to_string(),
+        }),
+        None => {}
+    }
+
+    let entries = match document.get("detectors") {
+        Some(toml::Value::Array(entries)) => entries.as_slice(),
+        Some(_) => {
+            issues.push(LintIssue {
+                level: LintLevel::Error,
+                detector: "<file>".to_string(),
+                message: "`detectors` must be a list of [[detectors]] tables".to_string(),
+            });
+            return Ok(issues);
+        }
+        None if ["extends", "disable", "profiles"]
+            .iter()
+            .any(|key| document.contains_key(*key)) =>
+        {
+            &[]
+        }
+        None => {
+            issues.push(LintIssue {
+                level: LintLevel::Error,
+                detector: "<file>".to_string(),
+                message: "no [[detectors]] entries found".to_string(),
+            });
+            return Ok(issues);
+        }
+    };
+
+    let mut first_entry_by_name: HashMap<&str, usize> = HashMap::new();
+    let mut first_entry_by_id: HashMap<&str, usize> = HashMap::new();
+    let mut detectors = Vec::new();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let name = entry.get("name").and_then(toml::Value::as_str);
+        let label = name
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("detectors[{index}]"));
+
+        if let Some(name) = name {
+            match first_entry_by_name.get(name) {
+                Some(first_index) => issues.push(LintIssue {
+                    level: LintLevel::Error,
+                    detector: label.clone(),
+                    message: format!("duplicate name, already used by detectors[{first_index}]"),
+                }),
+                None => {
+                    first_entry_by_name.insert(name, index);
+                }
+            }
+        }
+
+        if let Some(id) = entry.get("id").and_then(toml::Value::as_str) {
+            match first_entry_by_id.get(id) {
+                Some(first_index) => issues.push(LintIssue {
+                    level: LintLevel::Error,
+                    detector: label.clone(),
+                    message: format!(
+                        "duplicate id `{id}`, already used by detectors[{first_index}]"
+                    ),
+                }),
+                None => {
+                    first_entry_by_id.insert(id, index);
+                }
+            }
+        }
+
+        let Some(table) = entry.as_table() else {
+            issues.push(LintIssue {
+                level: LintLevel::Error,
+                detector: label,
+                message: "entry must be a table".to_string(),
+            });
+            continue;
+        };
+
+        let is_override = name.is_some_and(|name| inherited.iter().any(|known| known == name));
+        if let Some(detector) = lint_detector_entry(table, &label, is_override, &mut issues) {
+            detectors.push(detector);
+        }
+    }
+
+    lint_overlapping_detectors(&detectors, &mut issues);
+    Ok(issues)
+}
+
+fn lint_profiles(profiles: &toml::Table, issues: &mut Vec<LintIssue>) {
+    for (name, profile) in profiles {
+        let label = format!("profile `{name}`");
+        let Some(profile) = profile.as_table() else {
+            issues.push(LintIssue {
+                level: LintLevel::Error,
+                detector: label,
+                message: "profile must be a table".to_string(),
+            });
+            continue;
+        };
+
+        for (key, value) in profile {
+            if !PROFILE_KEYS.contains(&key.as_str()) {
+                issues.push(LintIssue {
+                    level: LintLevel::Warning,
+                    detector: label.clone(),
+                    message: format!("unknown key `{key}` is ignored"),
+                });
+            } else if value.clone().try_into::<Vec<String>>().is_err() {
+                issues.push(LintIssue {
+                    level: LintLevel::Error,
+                    detector: label.clone(),
+                    message: format!("`{key}` must be a list of strings"),
+                });
+            }
+        }
+    }
+}
+
+fn lint_detector_entry(
+    table: &toml::Table,
+    label: &str,
+    is_override: bool,
+    issues: &mut Vec<LintIssue>,
+) -> Option<RegexDetector> {
+    let mut error = |message: String| {
+        issues.push(LintIssue {
+            level: LintLevel::Error,
+            detector: label.to_string(),
+            message,
+        })
+    };
+
+    let mut fields = HashMap::new();
+    for key in ["name", "pattern", "finding_type", "severity"] {
+        match table.get(key) {
+            Some(toml::Value::String(value)) => {
+                fields.insert(key, value.as_str());
+            }
+            Some(_) => error(format!("`{key}` must be a string")),
+            None if is_override && key != "name" => {}
+            None => error(format!("missing required key `{key}`")),
+        }
+    }
+
+    for key in ["id", "description", "remediation"] {
+        if table.get(key).is_some_and(|value| !value.is_str()) {
+            error(format!("`{key}` must be a string"));
+        }
+    }
+
+    for key in ["tags", "references"] {
+        if table
+            .get(key)
+            .is_some_and(|value| value.clone().try_into::<Vec<String>>().is_err())
+        {
+            error(format!("`{key}` must be a list of strings"));
+        }
+    }
+
+    for key in ["paths", "exclude_paths"] {
+        let Some(value) = table.get(key) else {
+            continue;
+        };
+        match value.clone().try_into::<Vec<String>>() {
+            Ok(patterns) => {
+                if let Err(err) = compile_path_patterns(label, &patterns) {
+                    error(err);
+                }
+            }
+            Err(_) => error(format!("`{key}` must be a list of glob strings")),
+        }
+    }
+
+    let examples = match table.get("examples") {
+        Some(examples) => match examples.clone().try_into::<DetectorExamples>() {
+            Ok(parsed) => Some(parsed),
+            Err(err) => {
+                error(format!("`examples` is invalid: {}", err.message()));
+                None
+            }
+        },
+        None => Some(DetectorExamples::default()),
+    };
+
+    let severity = fields
+        .get("severity")
+        .and_then(|severity| match severity.parse::<Severity>() {
+            Ok(severity) => Some(severity),
+            Err(err) => {
+                error(err);
+                None
+            }
+        });
+
+    let regex = match fields.get("pattern").map(|pattern| Regex::new(pattern)) {
+        Some(Ok(regex)) => Some(regex),
+        Some(Err(err)) => {
+            error(format!("pattern does not compile: {err}"));
+            None
+        }
+        None => None,
+    };
+
+    if let Some(ref regex) = regex
+        && regex.is_match("")
+    {
+        error("pattern matches the empty string and would flag every line".to_string());
+    }
+
+    for key in table.keys() {
+        if !DETECTOR_KEYS.contains(&key.as_str()) {
+            issues.push(LintIssue {
+                level: LintLevel::Warning,
+                detector: label.to_string(),
+                message: format!("unknown key `{key}` is ignored"),
+            });
+        }
+    }
+
+    if let Some(offset) = fields
+        .get("pattern")
+        .and_then(|pattern| misplaced_case_insensitive_flag(pattern))
+    {
+        issues.push(LintIssue {
+            level: LintLevel::Warning,
+            detector: label.to_string(),
+            message: format!(
+                "`(?i)` at offset {offset} only applies to the rest of its group; move it to the start of the pattern or scope it with `(?i:...)`"
+            ),
+        });
+    }
+
+    let (Some(regex), Some(severity), Some(examples)) = (regex, severity, examples) else {
+        return None;
+    };
+    if fields.len() < 4 {
+        return None;
+    }
+
+    let mut detector = RegexDetector::new(
+        fields["name"],
+        regex.as_str(),
+        fields["finding_type"],
+        severity,
+    )
+    .ok()?;
+    detector.examples = examples;
+    Some(detector)
+}
+
+/// misplaced_case_insensitive_flag returns the offset of a `(?i)` that does not open the
+/// pattern or a group, i.e. one that silently leaves everything before it case-sensitive.
+fn misplaced_case_insensitive_flag(pattern: &str) -> Option<usize> {
+    let leading_flags = Regex::new(r"^(\(\?[a-zA-Z-]+\))*$").expect("valid flag regex");
+
+    pattern
+        .match_indices("(?i)")
+        .map(|(offset, _)| offset)
+        .find(|&offset| {
+            let prefix = &pattern[..offset];
+            !prefix.ends_with('(') && !leading_flags.is_match(prefix)
+        })
+}
+
+/// lint_overlapping_detectors flags identical patterns, and uses the declared examples to
+/// spot detectors that look like a strict subset of another detector reporting the same
+/// finding type. Specific rules nested inside broad catch-alls (e.g. Base64Detector) are
+/// intentional, so only same-type overlaps, which just duplicate findings, are reported.
+fn lint_overlapping_detectors(detectors: &[RegexDetector], issues: &mut Vec<LintIssue>) {
+    for (index, detector) in detectors.iter().enumerate() {
+        for (other_index, other) in detectors.iter().enumerate() {
+            if index == other_index {
+                continue;
+            }
+
+            if detector.regex.as_str() == other.regex.as_str() {
+                if other_index < index {
+                    issues.push(LintIssue {
+                        level: LintLevel::Warning,
+                        detector: detector.name.clone(),
+                        message: format!("pattern is identical to `{}`", other.name),
+                    });
+                }
+                continue;
+            }
+
+            if detector.finding_type == other.finding_type
+                && is_example_subset(detector, other)
+                && !is_example_subset(other, detector)
+            {
+                issues.push(LintIssue {
+                    level: LintLevel::Warning,
+                    detector: detector.name.clone(),
+                    message: format!(
+                        "looks like a strict subset of `{}`, which reports the same finding type: all of its positive examples match `{}`, but not the other way round",
+                        other.name, other.name
+                    ),
+                });
+            }
+        }
+    }
+}
+
+fn is_example_subset(detector: &RegexDetector, other: &RegexDetector) -> bool {
+    !detector.examples.positive.is_empty()
+        && !other.examples.positive.is_empty()
+        && detector
+            .examples
+            .positive
+            .iter()
+            .all(|example| other.matches_text(example))
+}
@@ -1,145 +1,808 @@
+use crate::archive::{self, ArchiveFormat, ArchiveLimits};
+use crate::binary;
+use crate::cache::{self, CachedMatch, ScanCache};
 use crate::cli::ScanArgs;
-use crate::detector::initialize_detectors;
-use crate::report::{Finding, ScanMetadata, Severity};
+use crate::config::DetectorSelection;
+use crate::detector::{Detector, DetectorRegistry, Match, ScanContext, initialize_detectors};
+use crate::encoding;
+use crate::error::{self, Error};
+use crate::report::{CacheStats, Finding, ScanMetadata, SkipReason, SkippedFile};
+use crate::utils::matches_path_patterns;
 use glob::Pattern;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::fs;
-use std::path::Path;
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
 
-pub fn run_scan(args: &ScanArgs) -> Result<(Vec<Finding>, ScanMetadata), String> {
-    let mut findings = Vec::new();
-    let mut files_scanned = 0;
-    let mut total_lines = 0;
-    let mut excluded_files = Vec::new();
+/// A compiled set of detectors and exclude patterns, ready to scan any number of inputs.
+/// Build one with `Scanner::builder()` and reuse it; nothing is reloaded between scans.
+pub struct Scanner {
+    registry: DetectorRegistry,
+    exclude_patterns: Vec<Pattern>,
+    max_file_size: Option<u64>,
+    scan_binaries: bool,
+    archive_limits: ArchiveLimits,
+    warnings: Vec<String>,
+}
 
-    let mut target_paths = Vec::new();
+/// Configures a Scanner. By default it runs the detectors from every detectors.toml layer
+/// on this machine, like `key-watch scan`.
+#[derive(Default)]
+pub struct ScannerBuilder {
+    registry: Option<DetectorRegistry>,
+    extra_detectors: DetectorRegistry,
+    profile: Option<String>,
+    filters: DetectorSelection,
+    excludes: Vec<String>,
+    max_file_size: Option<u64>,
+    scan_binaries: bool,
+    archive_limits: ArchiveLimits,
+}
 
-    for path_str in &args.paths {
-        let path = Path::new(path_str);
-        if path.is_file() {
-            target_paths.push((path_str.clone(), None));
-        } else if path.is_dir() {
-            collect_files(path_str, &mut target_paths, path_str);
-        } else {
-            // Push anyway, let read handle it or ignore
-            target_paths.push((path_str.clone(), None));
-        }
+impl ScannerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// detectors replaces the configured detectors with `registry`. Profile and filters
+    /// only apply to configured detectors, so they are ignored once this is set.
+    pub fn detectors(mut self, registry: DetectorRegistry) -> Self {
+        self.registry = Some(registry);
+        self
     }
 
-    target_paths.sort_by(|a, b| a.0.cmp(&b.0));
+    /// detector adds one detector on top of the configured (or given) set.
+    pub fn detector(mut self, detector: impl Detector + 'static) -> Self {
+        self.extra_detectors.register(detector);
+        self
+    }
 
-    let mut unique_paths: std::collections::BTreeMap<String, Vec<Option<String>>> =
-        std::collections::BTreeMap::new();
-    for (path, root) in target_paths {
-        let roots = unique_paths.entry(path).or_default();
-        if !roots.contains(&root) {
-            roots.push(root);
-        }
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
     }
-    let unique_paths: Vec<_> = unique_paths.into_iter().collect();
 
-    let detectors = initialize_detectors().map_err(|err| err.to_string())?;
-    let (multiline_detectors, line_detectors): (Vec<_>, Vec<_>) = detectors
-        .iter()
-        .partition(|detector| detector.regex.as_str().contains("(?s)"));
+    pub fn filters(mut self, filters: DetectorSelection) -> Self {
+        self.filters = filters;
+        self
+    }
 
-    let exclude_patterns: Vec<Pattern> = args
-        .exclude
-        .as_ref()
-        .map(|exclude_str| {
-            exclude_str
-                .split(',')
-                .filter(|pattern| !pattern.trim().is_empty())
-                .map(|pattern| {
-                    Pattern::new(pattern.trim())
-                        .map_err(|err| format!("Invalid exclude pattern '{}': {}", pattern, err))
+    /// exclude skips paths matching `pattern`, evaluated like `scan --exclude`.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.excludes.push(pattern.into());
+        self
+    }
+
+    /// max_file_size skips files larger than `bytes` instead of reading them.
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = Some(bytes);
+        self
+    }
+
+    /// scan_binaries scans the printable strings of binary files instead of skipping them.
+    pub fn scan_binaries(mut self, enabled: bool) -> Self {
+        self.scan_binaries = enabled;
+        self
+    }
+
+    /// archive_limits sets how deep the scanner opens nested archives and how much it
+    /// decompresses from each; see `ArchiveLimits::default()` for the defaults.
+    pub fn archive_limits(mut self, limits: ArchiveLimits) -> Self {
+        self.archive_limits = limits;
+        self
+    }
+
+    pub fn build(self) -> error::Result<Scanner> {
+        let (mut registry, warnings) = match self.registry {
+            Some(registry) => (registry, Vec::new()),
+            None => {
+                let configured = initialize_detectors(self.profile.as_deref(), &self.filters)?;
+                (
+                    DetectorRegistry::from(configured.detectors),
+                    configured.warnings,
+                )
+            }
+        };
+        registry.append(self.extra_detectors);
+
+        let exclude_patterns = self
+            .excludes
+            .iter()
+            .map(|pattern| {
+                Pattern::new(pattern).map_err(|source| Error::InvalidExclude {
+                    pattern: pattern.clone(),
+                    source,
                 })
-                .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+
+        Ok(Scanner {
+            registry,
+            exclude_patterns,
+            max_file_size: self.max_file_size,
+            scan_binaries: self.scan_binaries,
+            archive_limits: self.archive_limits,
+            warnings,
         })
-        .transpose()?
-        .unwrap_or_default();
+    }
+}
 
-    for (path, roots) in unique_paths {
-        if path_has_git_dir(Path::new(&path)) {
-            excluded_files.push(path);
-            continue;
+impl Scanner {
+    pub fn builder() -> ScannerBuilder {
+        ScannerBuilder::new()
+    }
+
+    pub fn detectors(&self) -> &DetectorRegistry {
+        &self.registry
+    }
+
+    /// warnings lists the changes untrusted project configs were not allowed to make to
+    /// the configured detectors. Every scan also reports them in `ScanMetadata::warnings`.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// max_file_size is the size above which files are skipped, if any.
+    pub fn max_file_size(&self) -> Option<u64> {
+        self.max_file_size
+    }
+
+    /// scan_path scans a file, or every file under a directory.
+    pub fn scan_path(&self, path: impl AsRef<Path>) -> error::Result<(Vec<Finding>, ScanMetadata)> {
+        let path = path.as_ref();
+        fs::metadata(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        self.scan_paths(&[path.to_string_lossy().into_owned()])
+    }
+
+    /// scan_paths scans several files and directories as one scan. A file reached through
+    /// overlapping roots is scanned once. Files that cannot be read as text, and symbolic
+    /// links met while walking a directory, are listed in `ScanMetadata::skipped_files`.
+    /// Archives are opened and their entries scanned under virtual paths such as
+    /// `dist/app.jar!/config/application.properties`; each archive and each entry scanned
+    /// counts as a file.
+    pub fn scan_paths(&self, paths: &[String]) -> error::Result<(Vec<Finding>, ScanMetadata)> {
+        self.scan_paths_with(paths, None)
+    }
+
+    /// scan_paths_cached scans like `scan_paths`, but takes the matches of files whose
+    /// content is unchanged from `cache` and stores the matches of every file it scans.
+    /// The cache must have been opened for this scanner's `cache::ruleset_hash`.
+    pub fn scan_paths_cached(
+        &self,
+        paths: &[String],
+        cache: &mut ScanCache,
+    ) -> error::Result<(Vec<Finding>, ScanMetadata)> {
+        let before = cache.stats();
+        let (findings, mut metadata) = self.scan_paths_with(paths, Some(cache))?;
+        let after = cache.stats();
+        metadata.cache = Some(CacheStats {
+            hits: after.hits - before.hits,
+            misses: after.misses - before.misses,
+        });
+        Ok((findings, metadata))
+    }
+
+    fn scan_paths_with(
+        &self,
+        paths: &[String],
+        mut cache: Option<&mut ScanCache>,
+    ) -> error::Result<(Vec<Finding>, ScanMetadata)> {
+        let mut findings = Vec::new();
+        let mut files_scanned = 0;
+        let mut total_lines = 0;
+        let mut excluded_files = Vec::new();
+        let mut skipped_files = Vec::new();
+
+        let mut target_paths = Vec::new();
+
+        for path_str in paths {
+            if Path::new(path_str).is_dir() {
+                collect_files(path_str, &mut target_paths, path_str);
+            } else {
+                // Missing and unreadable paths are reported as skipped by the read below.
+                target_paths.push((path_str.clone(), None));
+            }
         }
 
-        let should_exclude = matches_exclude_patterns(&path, &roots, &exclude_patterns);
+        target_paths.sort_by(|a, b| a.0.cmp(&b.0));
 
-        if should_exclude {
-            excluded_files.push(path);
-            continue;
+        let mut unique_paths: BTreeMap<String, Vec<Option<String>>> = BTreeMap::new();
+        for (path, root) in target_paths {
+            let roots = unique_paths.entry(path).or_default();
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
         }
 
-        let full_content = match fs::read(&path) {
-            Ok(bytes) => {
-                if bytes.contains(&0) {
-                    continue;
-                }
-                match String::from_utf8(bytes) {
-                    Ok(content) => content,
-                    Err(_) => continue,
+        for (path, roots) in unique_paths {
+            if self.is_excluded(&path, &roots) {
+                excluded_files.push(path);
+                continue;
+            }
+
+            match self.read_and_scan(&path, &roots, cache.as_deref_mut()) {
+                Ok(scan) => {
+                    files_scanned += scan.files;
+                    total_lines += scan.lines;
+                    findings.extend(scan.findings);
+                    excluded_files.extend(scan.excluded);
+                    skipped_files.extend(scan.skipped);
                 }
+                Err(reason) => skipped_files.push(SkippedFile { path, reason }),
+            }
+        }
+
+        let metadata = ScanMetadata {
+            files_scanned,
+            total_lines,
+            excluded_files,
+            skipped_files,
+            cache: None,
+            warnings: self.warnings.clone(),
+        };
+
+        Ok((findings, metadata))
+    }
+
+    /// is_excluded reports whether `scan_paths` would skip `path`, found under `roots`,
+    /// because of an exclude pattern or because it is inside a `.git` directory.
+    pub fn is_excluded(&self, path: &str, roots: &[Option<String>]) -> bool {
+        path_has_git_dir(Path::new(path))
+            || matches_path_patterns(path, roots, &self.exclude_patterns)
+    }
+
+    /// scan_file scans one file found under `roots` the way `scan_paths` scans each file
+    /// it visits. Returns None when the file is excluded, unreadable or binary.
+    pub fn scan_file(&self, path: &str, roots: &[Option<String>]) -> Option<Vec<Finding>> {
+        if self.is_excluded(path, roots) {
+            return None;
+        }
+        self.read_and_scan(path, roots, None)
+            .map(|scan| scan.findings)
+            .ok()
+    }
+
+    fn read_and_scan(
+        &self,
+        path: &str,
+        roots: &[Option<String>],
+        cache: Option<&mut ScanCache>,
+    ) -> Result<FileScan, SkipReason> {
+        let bytes = self.read_file(path, roots)?;
+        // Archives, like binary files, are not cached.
+        if self.opens_archive(&bytes) {
+            let mut scan = FileScan {
+                files: 1,
+                ..FileScan::default()
+            };
+            let mut remaining = self.archive_limits.max_total_size;
+            self.scan_archive(path, roots, &bytes, 1, &mut remaining, &mut scan)?;
+            return Ok(scan);
+        }
+        let content = match encoding::decode(&bytes) {
+            Ok(decoded) => decoded.text,
+            // Binary files have no lines to count, and are not cached.
+            Err(SkipReason::Binary) if self.scan_binaries => {
+                return Ok(FileScan::file(self.scan_binary(path, roots, &bytes), 0));
             }
-            Err(_) => continue,
+            Err(reason) => return Err(reason),
+        };
+        let content = content.as_ref();
+        let lines = content.lines().count();
+        let Some(cache) = cache else {
+            return Ok(FileScan::file(self.scan_text(path, roots, content), lines));
         };
 
-        files_scanned += 1;
-
-        for detector in &multiline_detectors {
-            if let Some(mat) = detector.regex.find(&full_content) {
-                let line_number = full_content[..mat.start()].matches('\n').count() + 1;
-                findings.push(Finding {
-                    file_path: path.clone(),
-                    line_number,
-                    matched_content: mat.as_str().to_string(),
-                    finding_type: detector.finding_type.clone(),
-                    severity: Severity::from_string(&detector.severity),
-                    plugin_name: detector.name.clone(),
+        let hash = cache::content_hash(&bytes);
+        if let Some(cached) = cache.lookup(path, roots, &hash)
+            && let Some(matches) = self.cached_matches(cached, content)
+        {
+            return Ok(FileScan::file(findings_from(path, matches), lines));
+        }
+        let matches = self.match_text(path, roots, content);
+        cache.store(
+            path,
+            roots,
+            hash,
+            content,
+            matches
+                .iter()
+                .map(|(detector, found)| (detector.name().to_string(), found.clone()))
+                .collect(),
+        );
+        Ok(FileScan::file(findings_from(path, matches), lines))
+    }
+
+    fn opens_archive(&self, bytes: &[u8]) -> bool {
+        self.archive_limits.max_depth > 0 && ArchiveFormat::detect(bytes).is_some()
+    }
+
+    /// scan_archive scans the entries of the archive at `path`, which is `depth` archives
+    /// deep. Entries that cannot be extracted are recorded as skipped under their virtual
+    /// path; the archive itself is only skipped when it cannot be opened.
+    fn scan_archive(
+        &self,
+        path: &str,
+        roots: &[Option<String>],
+        bytes: &[u8],
+        depth: usize,
+        remaining: &mut u64,
+        scan: &mut FileScan,
+    ) -> Result<(), SkipReason> {
+        for entry in archive::extract_entries(path, bytes, &self.archive_limits, remaining)? {
+            let entry_path = format!("{}{}{}", path, archive::ENTRY_SEPARATOR, entry.name);
+            if self.is_excluded(&entry_path, roots) {
+                scan.excluded.push(entry_path);
+                continue;
+            }
+            let scanned = entry.content.and_then(|content| {
+                self.scan_entry(&entry_path, roots, &content, depth, remaining, scan)
+            });
+            if let Err(reason) = scanned {
+                scan.skipped.push(SkippedFile {
+                    path: entry_path,
+                    reason,
                 });
             }
         }
+        Ok(())
+    }
+
+    /// scan_entry scans one file inside an archive `depth` archives deep, opening it in
+    /// turn if it is an archive too.
+    fn scan_entry(
+        &self,
+        path: &str,
+        roots: &[Option<String>],
+        content: &[u8],
+        depth: usize,
+        remaining: &mut u64,
+        scan: &mut FileScan,
+    ) -> Result<(), SkipReason> {
+        if self.opens_archive(content) {
+            if depth >= self.archive_limits.max_depth {
+                return Err(SkipReason::ArchiveDepth);
+            }
+            self.scan_archive(path, roots, content, depth + 1, remaining, scan)?;
+            scan.files += 1;
+            return Ok(());
+        }
+        match encoding::decode(content) {
+            Ok(decoded) => {
+                scan.files += 1;
+                scan.lines += decoded.text.lines().count();
+                scan.findings
+                    .extend(self.scan_text(path, roots, &decoded.text));
+            }
+            Err(SkipReason::Binary) if self.scan_binaries => {
+                scan.files += 1;
+                scan.findings.extend(self.scan_binary(path, roots, content));
+            }
+            // Packages are full of compiled code and images; listing every one as skipped
+            // would bury the entries that matter.
+            Err(SkipReason::Binary) => {}
+            Err(reason) => return Err(reason),
+        }
+        Ok(())
+    }
+
+    /// read_file reads a file to scan. Symbolic links are followed only when given
+    /// directly, not when found while walking a directory (every root is Some).
+    fn read_file(&self, path: &str, roots: &[Option<String>]) -> Result<Vec<u8>, SkipReason> {
+        let skip = |err: std::io::Error| SkipReason::from_io_error(&err);
+        let mut metadata = fs::symlink_metadata(path).map_err(skip)?;
+        if metadata.is_symlink() {
+            if !roots.contains(&None) {
+                return Err(SkipReason::Symlink);
+            }
+            metadata = fs::metadata(path).map_err(skip)?;
+        }
+        // Directories only get here when they could not be listed.
+        if metadata.is_dir() {
+            return Err(fs::read_dir(path)
+                .err()
+                .map_or(SkipReason::Unreadable, skip));
+        }
+        if self
+            .max_file_size
+            .is_some_and(|max_file_size| metadata.len() > max_file_size)
+        {
+            return Err(SkipReason::TooLarge);
+        }
+        fs::read(path).map_err(skip)
+    }
+
+    /// cached_matches pairs cached matches with their detectors and reads their text back
+    /// from `content`. Returns None if a detector is no longer part of this scanner or a
+    /// match is not where the cache says.
+    fn cached_matches(
+        &self,
+        cached: &[CachedMatch],
+        content: &str,
+    ) -> Option<Vec<(&dyn Detector, Match)>> {
+        cached
+            .iter()
+            .map(|found| {
+                let detector = self
+                    .registry
+                    .iter()
+                    .find(|detector| detector.name() == found.detector)?;
+                Some((
+                    detector,
+                    Match {
+                        line_number: found.line_number,
+                        matched_content: found.matched_content(content)?.to_string(),
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// scan_bytes scans in-memory content. `name` is reported as the file path and drives
+    /// path-scoped detectors; exclude patterns only apply to entries of archives. Binary
+    /// content yields nothing unless the scanner scans binaries.
+    pub fn scan_bytes(&self, name: &str, bytes: &[u8]) -> Vec<Finding> {
+        self.scan_bytes_under(name, &[None], bytes)
+    }
+
+    /// scan_bytes_under scans in-memory content like `scan_bytes`, as if `name` had been
+    /// found under `roots`, so detector path globs relative to a root apply as in `scan`.
+    pub fn scan_bytes_under(
+        &self,
+        name: &str,
+        roots: &[Option<String>],
+        bytes: &[u8],
+    ) -> Vec<Finding> {
+        let mut scan = FileScan::default();
+        let mut remaining = self.archive_limits.max_total_size;
+        // Unreadable content and entries simply yield no findings here.
+        let _ = self.scan_entry(name, roots, bytes, 0, &mut remaining, &mut scan);
+        scan.findings
+    }
+
+    /// scan_reader reads `reader` to the end and scans it like `scan_bytes`.
+    pub fn scan_reader(&self, name: &str, mut reader: impl Read) -> error::Result<Vec<Finding>> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(|source| Error::Io {
+            path: name.into(),
+            source,
+        })?;
+        Ok(self.scan_bytes(name, &bytes))
+    }
+
+    /// scan_stream scans `reader` one line at a time and hands each finding to `on_finding`
+    /// as soon as its line is read, so unbounded input such as `tail -f` works. Detectors
+    /// whose matches span lines see the last STREAM_WINDOW_LINES lines, up to
+    /// STREAM_WINDOW_BYTES, and find secrets that fit in that window. A `name` matching an
+    /// exclude pattern is not read at all.
+    pub fn scan_stream(
+        &self,
+        name: &str,
+        mut reader: impl BufRead,
+        mut on_finding: impl FnMut(Finding),
+    ) -> error::Result<ScanMetadata> {
+        if self.is_excluded(name, &[None]) {
+            return Ok(ScanMetadata {
+                excluded_files: vec![name.to_string()],
+                warnings: self.warnings.clone(),
+                ..ScanMetadata::default()
+            });
+        }
+
+        let mut line = Vec::new();
+        let mut line_number = 0;
+        let (spanning, line_detectors): (Vec<&dyn Detector>, Vec<&dyn Detector>) = self
+            .registry
+            .iter()
+            .filter(|detector| detector.applies_to(name, &[None]))
+            .partition(|detector| detector.spans_lines());
+        let mut window = StreamWindow::default();
+
+        loop {
+            line.clear();
+            let read = reader
+                .read_until(b'\n', &mut line)
+                .map_err(|source| Error::Io {
+                    path: name.into(),
+                    source,
+                })?;
+            if read == 0 {
+                break;
+            }
+            line_number += 1;
 
-        for (line_idx, line) in full_content.lines().enumerate() {
-            total_lines += 1;
-            for detector in &line_detectors {
-                if let Some(mat) = detector.regex.find(line) {
-                    findings.push(Finding {
-                        file_path: path.clone(),
-                        line_number: line_idx + 1,
-                        matched_content: mat.as_str().to_string(),
-                        finding_type: detector.finding_type.clone(),
-                        severity: Severity::from_string(&detector.severity),
-                        plugin_name: detector.name.clone(),
-                    });
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches(['\n', '\r']);
+            let ctx = ScanContext {
+                path: name,
+                content: text,
+            };
+            for &detector in &line_detectors {
+                for found in detector.scan(&ctx) {
+                    let mut finding = new_finding(name, detector, found);
+                    finding.line_number = line_number;
+                    on_finding(finding);
+                }
+            }
+
+            if spanning.is_empty() {
+                continue;
+            }
+            window.push(line_number, text);
+            let ctx = ScanContext {
+                path: name,
+                content: &window.text(),
+            };
+            for (index, &detector) in spanning.iter().enumerate() {
+                for found in detector.find_all(&ctx) {
+                    let start = window.first_line + found.line_number.saturating_sub(1);
+                    if window.reported.insert((index, start)) {
+                        let mut finding = new_finding(name, detector, found);
+                        finding.line_number = start;
+                        on_finding(finding);
+                    }
                 }
             }
         }
+
+        Ok(ScanMetadata {
+            files_scanned: 1,
+            total_lines: line_number,
+            warnings: self.warnings.clone(),
+            ..ScanMetadata::default()
+        })
+    }
+
+    fn scan_text(&self, path: &str, roots: &[Option<String>], content: &str) -> Vec<Finding> {
+        findings_from(path, self.match_text(path, roots, content))
+    }
+
+    /// scan_binary scans the printable strings of binary content, one string per line, and
+    /// reports byte offsets in place of line numbers.
+    fn scan_binary(&self, path: &str, roots: &[Option<String>], bytes: &[u8]) -> Vec<Finding> {
+        let strings = binary::extract_strings(bytes, binary::MIN_STRING_LEN);
+        let text = strings
+            .iter()
+            .map(|string| string.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut findings: Vec<Finding> = self
+            .match_text(path, roots, &text)
+            .into_iter()
+            .filter_map(|(detector, found)| {
+                // A custom detector may report a line that is not one of the strings.
+                let string = found
+                    .line_number
+                    .checked_sub(1)
+                    .and_then(|index| strings.get(index))?;
+                // Strings are ASCII, so a byte index into the text is a character index.
+                let first_line = found.matched_content.lines().next().unwrap_or_default();
+                let index = string.text.find(first_line).unwrap_or(0);
+                let mut finding = new_finding(path, detector, found);
+                finding.line_number = 0;
+                finding.byte_offset = Some(string.byte_offset(index));
+                Some(finding)
+            })
+            .collect();
+        findings.sort_by_key(|finding| finding.byte_offset);
+        findings
+    }
+
+    fn match_text(
+        &self,
+        path: &str,
+        roots: &[Option<String>],
+        content: &str,
+    ) -> Vec<(&dyn Detector, Match)> {
+        let ctx = ScanContext { path, content };
+        self.registry
+            .iter()
+            .filter(|detector| detector.applies_to(path, roots))
+            .flat_map(|detector| {
+                detector
+                    .scan(&ctx)
+                    .into_iter()
+                    .map(move |found| (detector, found))
+            })
+            .collect()
+    }
+}
+
+/// Lines a streaming scan keeps for detectors whose matches span lines.
+pub const STREAM_WINDOW_LINES: usize = 100;
+
+/// Bytes a streaming scan keeps for detectors whose matches span lines; older lines are
+/// dropped first, but the latest line is always kept.
+pub const STREAM_WINDOW_BYTES: usize = 64 * 1024;
+
+/// The most recent lines of a stream, which detectors spanning lines scan as one text
+/// after every line. A match is reported once, by the line it starts on.
+#[derive(Default)]
+struct StreamWindow {
+    lines: VecDeque<String>,
+    bytes: usize,
+    /// Line number of the first line kept.
+    first_line: usize,
+    /// Detector index and start line of every match reported from lines still kept.
+    reported: BTreeSet<(usize, usize)>,
+}
+
+impl StreamWindow {
+    fn push(&mut self, line_number: usize, text: &str) {
+        if self.lines.is_empty() {
+            self.first_line = line_number;
+        }
+        self.bytes += text.len() + 1;
+        self.lines.push_back(text.to_string());
+        while self.lines.len() > 1
+            && (self.lines.len() > STREAM_WINDOW_LINES || self.bytes > STREAM_WINDOW_BYTES)
+        {
+            if let Some(dropped) = self.lines.pop_front() {
+                self.bytes -= dropped.len() + 1;
+                self.first_line += 1;
+            }
+        }
+        let first_line = self.first_line;
+        self.reported.retain(|&(_, start)| start >= first_line);
+    }
+
+    fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// What scanning one file produced. An archive contributes every entry inside it.
+#[derive(Default)]
+struct FileScan {
+    findings: Vec<Finding>,
+    files: usize,
+    lines: usize,
+    excluded: Vec<String>,
+    skipped: Vec<SkippedFile>,
+}
+
+impl FileScan {
+    fn file(findings: Vec<Finding>, lines: usize) -> Self {
+        Self {
+            findings,
+            files: 1,
+            lines,
+            ..Self::default()
+        }
+    }
+}
+
+fn findings_from(path: &str, matches: Vec<(&dyn Detector, Match)>) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = matches
+        .into_iter()
+        .map(|(detector, found)| new_finding(path, detector, found))
+        .collect();
+    findings.sort_by_key(|finding| finding.line_number);
+    findings
+}
+
+/// run_scan runs `key-watch scan`: it builds a Scanner from the command-line options and
+/// scans every path given. A cache that cannot be used or saved does not fail the scan; it
+/// is reported in `ScanMetadata::warnings`.
+pub fn run_scan(args: &ScanArgs) -> error::Result<(Vec<Finding>, ScanMetadata)> {
+    let scanner = build_scanner(args)?;
+    if !args.cache {
+        return scanner.scan_paths(&args.paths);
     }
 
-    let metadata = ScanMetadata {
-        files_scanned,
-        total_lines,
-        excluded_files,
+    let uncached = |warning: &str| {
+        let (findings, mut metadata) = scanner.scan_paths(&args.paths)?;
+        metadata.warnings.push(warning.to_string());
+        Ok((findings, metadata))
+    };
+    let Some(ruleset) = cache::ruleset_hash(&scanner) else {
+        return uncached("--cache ignored; a detector does not support caching");
     };
+    let Some(dir) = args
+        .cache_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(cache::default_dir)
+    else {
+        return uncached("--cache ignored; no cache directory found, pass --cache-dir");
+    };
+
+    let mut scan_cache = ScanCache::open(&dir, ruleset);
+    let (findings, mut metadata) = scanner.scan_paths_cached(&args.paths, &mut scan_cache)?;
+    if let Err(err) = scan_cache.save() {
+        metadata
+            .warnings
+            .push(format!("scan cache not saved: {}", err));
+    }
+    Ok((findings, metadata))
+}
 
+/// run_stream_scan runs `key-watch scan -`: it scans `reader` line by line, passing each
+/// finding to `on_finding` as it appears, and returns them all once the input ends.
+pub fn run_stream_scan(
+    args: &ScanArgs,
+    reader: impl BufRead,
+    mut on_finding: impl FnMut(&Finding),
+) -> error::Result<(Vec<Finding>, ScanMetadata)> {
+    let name = args.stdin_filename.as_deref().unwrap_or(STDIN_NAME);
+    let mut findings = Vec::new();
+    let metadata = build_scanner(args)?.scan_stream(name, reader, |finding| {
+        on_finding(&finding);
+        findings.push(finding);
+    })?;
     Ok((findings, metadata))
 }
 
-fn collect_files(dir_path: &str, files: &mut Vec<(String, Option<String>)>, root: &str) {
-    if let Ok(entries) = fs::read_dir(dir_path) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(path_str) = path.to_str() {
-                    files.push((path_str.to_string(), Some(root.to_string())));
+/// The file name reported for standard input without `--stdin-filename`.
+pub const STDIN_NAME: &str = "<stdin>";
+
+fn build_scanner(args: &ScanArgs) -> error::Result<Scanner> {
+    let mut builder = Scanner::builder().filters(args.detector_filters());
+    if let Some(ref profile) = args.profile {
+        builder = builder.profile(profile);
+    }
+    if args.scan_binaries {
+        builder = builder.scan_binaries(true);
+    }
+    if let Some(max_file_size) = args.max_file_size {
+        builder = builder.max_file_size(max_file_size);
+    }
+    builder = builder.archive_limits(ArchiveLimits {
+        max_depth: args.archive_depth,
+        max_total_size: args.max_archive_size,
+        max_ratio: args.max_compression_ratio,
+    });
+    if let Some(ref exclude_str) = args.exclude {
+        for pattern in exclude_str.split(',').map(str::trim) {
+            if !pattern.is_empty() {
+                builder = builder.exclude(pattern);
+            }
+        }
+    }
+    builder.build()
+}
+
+fn new_finding(path: &str, detector: &dyn Detector, found: Match) -> Finding {
+    Finding {
+        file_path: path.to_string(),
+        line_number: found.line_number,
+        byte_offset: None,
+        matched_content: found.matched_content,
+        finding_type: detector.finding_type().to_string(),
+        severity: detector.severity(),
+        plugin_name: detector.name().to_string(),
+        metadata: detector.metadata().clone(),
+    }
+}
+
+/// collect_files lists the files under `dir_path`. Symbolic links are listed rather than
+/// followed, and a directory that cannot be read is listed itself, so that the scan can
+/// report both as skipped.
+pub(crate) fn collect_files(dir_path: &str, files: &mut Vec<(String, Option<String>)>, root: &str) {
+    let Ok(entries) = fs::read_dir(dir_path) else {
+        files.push((dir_path.to_string(), Some(root.to_string())));
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(path_str) = path.to_str() else {
+            continue;
+        };
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => {
+                if path.file_name().is_none_or(|name| name != ".git") {
+                    collect_files(path_str, files, root);
                 }
-            } else if path.is_dir()
-                && path.file_name().is_none_or(|name| name != ".git")
-                && let Some(path_str) = path.to_str()
-            {
-                collect_files(path_str, files, root);
             }
+            _ => files.push((path_str.to_string(), Some(root.to_string()))),
         }
     }
 }
@@ -148,25 +811,3 @@ fn path_has_git_dir(path: &Path) -> bool {
     path.components()
         .any(|component| component.as_os_str() == ".git")
 }
-
-fn matches_exclude_patterns(
-    path: &str,
-    scan_roots: &[Option<String>],
-    patterns: &[Pattern],
-) -> bool {
-    let path = Path::new(path);
-
-    patterns.iter().any(|pattern| {
-        pattern.matches_path(path)
-            || path
-                .file_name()
-                .and_then(|name| name.to_str())
-                .is_some_and(|name| pattern.matches(name))
-            || scan_roots.iter().any(|root_opt| {
-                root_opt
-                    .as_deref()
-                    .and_then(|root| path.strip_prefix(root).ok())
-                    .is_some_and(|relative| pattern.matches_path(relative))
-            })
-    })
-}
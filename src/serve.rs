@@ -0,0 +1,474 @@
+use crate::encoding;
+use crate::error::{self, Error};
+use crate::report::{self, DetectorMetadata, ScanMetadata, Severity};
+use crate::scanner::Scanner;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Default address for `key-watch serve`.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8484";
+/// Default limit on the size of a request body.
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+/// Default limit on connections handled at once.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+/// Default time a client has to send its whole request.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Environment variable holding the bearer token when `--token-file` is not given.
+pub const TOKEN_ENV: &str = "KEYWATCH_SERVE_TOKEN";
+
+/// Request line and headers larger than this are rejected.
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+const MAX_HEADERS: usize = 64;
+/// How long writing the 503 to a connection over the limit may take.
+const REJECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Most unread request bytes discarded before a connection is closed.
+const DRAIN_LIMIT: usize = 64 * 1024;
+
+/// Limits and access control for the HTTP API.
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    pub max_body_bytes: usize,
+    /// When set, every request must send `Authorization: Bearer <token>`.
+    pub token: Option<String>,
+    /// Connections handled at once; further connections get `503` and are closed.
+    pub max_connections: usize,
+    /// Time a client has to send its whole request, headers and body, before it gets `408`.
+    pub request_timeout: Duration,
+    /// Directory request file names are resolved against for detector path globs relative
+    /// to a root, like the directory given to `scan`. The CLI uses its working directory.
+    pub scan_root: Option<String>,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions {
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            token: None,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            scan_root: None,
+        }
+    }
+}
+
+/// An HTTP server answering `POST /scan` and `GET /rules` with one compiled scanner.
+pub struct Server {
+    listener: TcpListener,
+    state: Arc<State>,
+}
+
+struct State {
+    scanner: Scanner,
+    options: ServeOptions,
+    /// Connections being handled right now.
+    active: AtomicUsize,
+}
+
+/// Holds one of the `max_connections` slots, giving it back when dropped.
+struct ConnectionSlot(Arc<State>);
+
+impl ConnectionSlot {
+    fn acquire(state: &Arc<State>) -> Option<ConnectionSlot> {
+        state
+            .active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |active| {
+                (active < state.options.max_connections).then_some(active + 1)
+            })
+            .ok()
+            .map(|_| ConnectionSlot(Arc::clone(state)))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Server {
+    /// bind listens on `address`; port 0 picks a free port, see `local_addr`.
+    pub fn bind(address: &str, scanner: Scanner, options: ServeOptions) -> error::Result<Server> {
+        let listener = TcpListener::bind(address).map_err(|source| Error::Listen {
+            address: address.to_string(),
+            source,
+        })?;
+        Ok(Server {
+            listener,
+            state: Arc::new(State {
+                scanner,
+                options,
+                active: AtomicUsize::new(0),
+            }),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// run accepts connections until the process exits, answering each on its own thread.
+    /// At most `max_connections` are handled at once; the rest are answered with `503`
+    /// straight away. Every response closes the connection.
+    pub fn run(self) {
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(mut stream) => match ConnectionSlot::acquire(&self.state) {
+                    Some(slot) => {
+                        std::thread::spawn(move || {
+                            handle_connection(&mut stream, &slot.0);
+                            // Free the slot before the client sees the connection close.
+                            drop(slot);
+                        });
+                    }
+                    None => {
+                        let _ = stream.set_write_timeout(Some(REJECT_TIMEOUT));
+                        let response = Response::error(503, "too many connections")
+                            .with_header("Retry-After", "1");
+                        let _ = write_response(&mut stream, &response);
+                        finish_connection(&mut stream);
+                    }
+                },
+                Err(err) => eprintln!("WARNING: failed to accept connection: {}", err),
+            }
+        }
+    }
+}
+
+/// A request body for `POST /scan` sent as `application/json`.
+#[derive(Deserialize)]
+struct ScanRequest {
+    filename: Option<String>,
+    content: String,
+}
+
+/// An entry of the `GET /rules` response.
+#[derive(Serialize)]
+struct RuleInfo<'a> {
+    name: &'a str,
+    finding_type: &'a str,
+    severity: Severity,
+    #[serde(flatten)]
+    metadata: &'a DetectorMetadata,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    content_type: Option<String>,
+    filename: Option<String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: String) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, serde_json::json!({ "error": message }).to_string())
+    }
+
+    fn with_header(mut self, name: &'static str, value: &str) -> Response {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+fn handle_connection(stream: &mut TcpStream, state: &State) {
+    // Without a deadline a client sending a byte at a time would hold its thread forever.
+    let deadline = Instant::now() + state.options.request_timeout;
+    let response = match read_request(stream, &state.options, deadline) {
+        Ok(request) => respond(&request, state),
+        Err(response) => response,
+    };
+    let _ = stream.set_write_timeout(Some(state.options.request_timeout));
+    let _ = write_response(stream, &response);
+    finish_connection(stream);
+}
+
+/// finish_connection ends the response and discards request bytes that arrived but were
+/// not read, such as the body of a rejected request. Closing a socket with unread input
+/// resets the connection, which can drop the response before the client reads it. Only
+/// what already arrived is drained, so a client that keeps sending cannot hold it open.
+fn finish_connection(stream: &mut TcpStream) {
+    let _ = stream.shutdown(Shutdown::Write);
+    if stream.set_nonblocking(true).is_err() {
+        return;
+    }
+    let mut buffer = [0; 4096];
+    let mut drained = 0;
+    while drained < DRAIN_LIMIT {
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(read) => drained += read,
+        }
+    }
+}
+
+fn respond(request: &Request, state: &State) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/scan") => scan(request, state),
+        ("GET", "/rules") => rules(&state.scanner),
+        (_, "/scan") => Response::error(405, "use POST /scan").with_header("Allow", "POST"),
+        (_, "/rules") => Response::error(405, "use GET /rules").with_header("Allow", "GET"),
+        _ => Response::error(404, "not found"),
+    }
+}
+
+fn scan(request: &Request, state: &State) -> Response {
+    let start = Instant::now();
+    let is_json = request.content_type.as_deref().is_some_and(|value| {
+        value
+            .split(';')
+            .next()
+            .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+    });
+
+    let (name, content) = if is_json {
+        match serde_json::from_slice::<ScanRequest>(&request.body) {
+            Ok(scan_request) => (scan_request.filename, scan_request.content.into_bytes()),
+            Err(err) => return Response::error(400, &format!("invalid JSON body: {}", err)),
+        }
+    } else {
+        let name = request
+            .query
+            .as_deref()
+            .and_then(|query| query_param(query, "filename"))
+            .or_else(|| request.filename.clone());
+        (name, request.body.clone())
+    };
+    let name = name.unwrap_or_else(|| "<request>".to_string());
+
+    let roots = [state.options.scan_root.clone()];
+    let findings = state.scanner.scan_bytes_under(&name, &roots, &content);
+    let metadata = match encoding::decode(&content) {
+        Ok(decoded) => ScanMetadata {
+            files_scanned: 1,
+            total_lines: decoded.text.lines().count(),
+            ..ScanMetadata::default()
+        },
+        Err(_) => ScanMetadata::default(),
+    };
+    let scan_time = report::format_scan_time(start.elapsed());
+    match report::create_report(findings, metadata, scan_time) {
+        Ok(body) => Response::json(200, body),
+        Err(err) => Response::error(500, &format!("failed to serialize report: {}", err)),
+    }
+}
+
+fn rules(scanner: &Scanner) -> Response {
+    let rules: Vec<RuleInfo> = scanner
+        .detectors()
+        .iter()
+        .map(|detector| RuleInfo {
+            name: detector.name(),
+            finding_type: detector.finding_type(),
+            severity: detector.severity(),
+            metadata: detector.metadata(),
+        })
+        .collect();
+    match serde_json::to_string_pretty(&rules) {
+        Ok(body) => Response::json(200, body),
+        Err(err) => Response::error(500, &format!("failed to serialize rules: {}", err)),
+    }
+}
+
+/// read_request reads one request by `deadline`, or returns the error response to send
+/// instead. The bearer token and the body size are checked as soon as the headers are in,
+/// before any of the body is read.
+fn read_request(
+    stream: &mut TcpStream,
+    options: &ServeOptions,
+    deadline: Instant,
+) -> Result<Request, Response> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 8192];
+
+    loop {
+        let read = read_by(stream, &mut chunk, deadline)
+            .map_err(|_| Response::error(408, "timed out reading the request"))?;
+        if read == 0 {
+            return Err(Response::error(400, "incomplete request"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut parsed = httparse::Request::new(&mut headers);
+        let header_len = match parsed.parse(&buffer) {
+            Ok(httparse::Status::Complete(len)) => len,
+            Ok(httparse::Status::Partial) if buffer.len() > MAX_HEADER_BYTES => {
+                return Err(Response::error(431, "request headers too large"));
+            }
+            Ok(httparse::Status::Partial) => continue,
+            Err(err) => return Err(Response::error(400, &format!("malformed request: {}", err))),
+        };
+
+        let header = |name: &str| {
+            parsed
+                .headers
+                .iter()
+                .find(|header| header.name.eq_ignore_ascii_case(name))
+                .map(|header| String::from_utf8_lossy(header.value).into_owned())
+        };
+        if let Some(ref token) = options.token {
+            let authorization = header("Authorization");
+            let presented = authorization
+                .as_deref()
+                .and_then(|value| value.strip_prefix("Bearer "));
+            if !presented.is_some_and(|presented| constant_time_eq(presented.trim(), token)) {
+                return Err(Response::error(401, "missing or invalid bearer token")
+                    .with_header("WWW-Authenticate", "Bearer"));
+            }
+        }
+        if header("Transfer-Encoding").is_some() {
+            return Err(Response::error(
+                411,
+                "chunked bodies are not supported; send Content-Length",
+            ));
+        }
+        let content_length = match header("Content-Length") {
+            Some(value) => value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| Response::error(400, "invalid Content-Length"))?,
+            None => 0,
+        };
+        if content_length > options.max_body_bytes {
+            return Err(Response::error(
+                413,
+                &format!("request body exceeds {} bytes", options.max_body_bytes),
+            ));
+        }
+
+        let (path, query) = match parsed.path.unwrap_or("/").split_once('?') {
+            Some((path, query)) => (path.to_string(), Some(query.to_string())),
+            None => (parsed.path.unwrap_or("/").to_string(), None),
+        };
+        let mut request = Request {
+            method: parsed.method.unwrap_or_default().to_string(),
+            path,
+            query,
+            content_type: header("Content-Type"),
+            filename: header("X-Filename"),
+            body: buffer[header_len..].to_vec(),
+        };
+
+        while request.body.len() < content_length {
+            let read = read_by(stream, &mut chunk, deadline)
+                .map_err(|_| Response::error(408, "timed out reading the request body"))?;
+            if read == 0 {
+                return Err(Response::error(
+                    400,
+                    "request body shorter than Content-Length",
+                ));
+            }
+            request.body.extend_from_slice(&chunk[..read]);
+        }
+        request.body.truncate(content_length);
+        return Ok(request);
+    }
+}
+
+/// read_by reads from `stream`, failing with `TimedOut` once `deadline` has passed.
+fn read_by(stream: &mut TcpStream, buffer: &mut [u8], deadline: Instant) -> io::Result<usize> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(io::ErrorKind::TimedOut.into());
+    }
+    stream.set_read_timeout(Some(remaining))?;
+    stream.read(buffer)
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// query_param returns the percent-decoded value of `name` in a query string.
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode(value))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (escaped, bytes[i]) {
+            (Some(byte), _) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (None, b'+') => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (None, byte) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// constant_time_eq compares tokens without revealing how long a common prefix is.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Repositories and config hashes whose `detectors.toml` may disable or weaken detectors.
+/// Stored as `trust.toml` in the user config dir.
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct TrustStore {
+    #[serde(default)]
+    pub directories: Vec<String>,
+    #[serde(default)]
+    pub hashes: Vec<String>,
+}
+
+/// trust_store_path returns where trust records are kept, or None without a user config dir.
+pub fn trust_store_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|p| p.join("keywatch").join("trust.toml"))
+}
+
+impl TrustStore {
+    /// load reads the trust store, treating a missing file as empty.
+    pub fn load() -> Result<TrustStore, String> {
+        match trust_store_path() {
+            Some(path) if path.exists() => {
+                let contents = fs::read_to_string(&path)
+                    .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+                toml::from_str(&contents)
+                    .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))
+            }
+            _ => Ok(TrustStore::default()),
+        }
+    }
+
+    pub fn save(&self) -> Result<PathBuf, String> {
+        let path = trust_store_path()
+            .ok_or_else(|| "Could not determine the user config directory".to_string())?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| format!("Failed to create {}: {}", parent.display(), err))?;
+        }
+        let contents = toml::to_string(self)
+            .map_err(|err| format!("Failed to serialize trust store: {}", err))?;
+        fs::write(&path, contents)
+            .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+        Ok(path)
+    }
+
+    /// trusts reports whether `config_path` sits in a trusted directory or has a trusted hash.
+    pub fn trusts(&self, config_path: &Path) -> bool {
+        let directory_trusted =
+            config_directory(config_path).is_some_and(|dir| self.directories.contains(&dir));
+
+        directory_trusted
+            || config_digest(config_path).is_ok_and(|digest| self.hashes.contains(&digest))
+    }
+
+    /// trust_directory records a trusted directory; returns false if it already was.
+    pub fn trust_directory(&mut self, directory: String) -> bool {
+        add_record(&mut self.directories, directory)
+    }
+
+    /// trust_hash records a trusted config digest; returns false if it already was.
+    pub fn trust_hash(&mut self, digest: String) -> bool {
+        add_record(&mut self.hashes, digest)
+    }
+
+    /// untrust drops every record that trusts `config_path`; returns false if none did.
+    pub fn untrust(&mut self, config_path: &Path) -> bool {
+        let before = self.directories.len() + self.hashes.len();
+        if let Some(dir) = config_directory(config_path) {
+            self.directories.retain(|trusted| *trusted != dir);
+        }
+        if let Ok(digest) = config_digest(config_path) {
+            self.hashes.retain(|trusted| *trusted != digest);
+        }
+        self.directories.len() + self.hashes.len() < before
+    }
+}
+
+fn add_record(records: &mut Vec<String>, value: String) -> bool {
+    if records.contains(&value) {
+        return false;
+    }
+    records.push(value);
+    true
+}
+
+/// is_trusted checks `config_path` against the user's trust store.
+pub fn is_trusted(config_path: &Path) -> bool {
+    TrustStore::load().is_ok_and(|store| store.trusts(config_path))
+}
+
+/// config_directory is the canonical directory a config file lives in, as stored in trust records.
+pub fn config_directory(config_path: &Path) -> Option<String> {
+    let canonical = fs::canonicalize(config_path).ok()?;
+    canonical
+        .parent()
+        .map(|dir| dir.to_string_lossy().into_owned())
+}
+
+/// config_digest hashes a config file together with every file it `extends`, so a
+/// hash-trusted config stops being trusted as soon as any part of it changes.
+pub fn config_digest(config_path: &Path) -> Result<String, String> {
+    let mut hasher = Sha256::new();
+    let mut visited = Vec::new();
+    hash_config(config_path, &mut hasher, &mut visited)?;
+
+    let digest = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    Ok(format!("sha256:{digest}"))
+}
+
+fn hash_config(path: &Path, hasher: &mut Sha256, visited: &mut Vec<PathBuf>) -> Result<(), String> {
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if visited.contains(&canonical) {
+        return Ok(());
+    }
+    visited.push(canonical);
+
+    let contents =
+        fs::read(path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    hasher.update((contents.len() as u64).to_le_bytes());
+    hasher.update(&contents);
+
+    #[derive(Deserialize)]
+    struct Extends {
+        #[serde(default)]
+        extends: Vec<String>,
+    }
+    let extends = toml::from_str::<Extends>(&String::from_utf8_lossy(&contents))
+        .map(|file| file.extends)
+        .unwrap_or_default();
+    for extended in extends {
+        let extended_path = path
+            .parent()
+            .map_or_else(|| PathBuf::from(&extended), |dir| dir.join(&extended));
+        hash_config(&extended_path, hasher, visited)?;
+    }
+    Ok(())
+}
@@ -1,5 +1,7 @@
+use glob::Pattern;
 use std::fs::File;
 use std::io::{Result, Write};
+use std::path::Path;
 
 /// write_to_file writes the given content to a file at the specified path.
 pub fn write_to_file(path: &str, content: &str) -> Result<()> {
@@ -22,3 +24,27 @@ pub fn make_executable(path: &str) -> Result<()> {
 pub fn make_executable(_path: &str) -> Result<()> {
     Ok(())
 }
+
+/// matches_path_patterns checks a path against glob patterns. A pattern matches the full
+/// path, the file name alone, or the path relative to any of the scan roots it was found under.
+pub fn matches_path_patterns(
+    path: &str,
+    scan_roots: &[Option<String>],
+    patterns: &[Pattern],
+) -> bool {
+    let path = Path::new(path);
+
+    patterns.iter().any(|pattern| {
+        pattern.matches_path(path)
+            || path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| pattern.matches(name))
+            || scan_roots.iter().any(|root_opt| {
+                root_opt
+                    .as_deref()
+                    .and_then(|root| path.strip_prefix(root).ok())
+                    .is_some_and(|relative| pattern.matches_path(relative))
+            })
+    })
+}
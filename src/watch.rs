@@ -0,0 +1,261 @@
+use crate::archive;
+use crate::error::{self, Error};
+use crate::report::Finding;
+use crate::scanner::{Scanner, collect_files};
+use notify::{Config, Event, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, channel};
+use std::time::{Duration, Instant};
+
+/// How often the polling fallback checks watched paths for changes.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many debounce periods a burst of events may last before the changed paths are
+/// rescanned anyway.
+pub const MAX_BURST_DEBOUNCES: u32 = 10;
+
+/// A change in the findings of a watched file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindingChange {
+    New(Finding),
+    Resolved(Finding),
+}
+
+/// diff_findings compares a file's findings before and after a change. Findings are
+/// matched by detector and matched content, so secrets that only moved to another line
+/// are neither new nor resolved.
+pub fn diff_findings(before: &[Finding], after: &[Finding]) -> Vec<FindingChange> {
+    let key = |finding: &Finding| (finding.plugin_name.clone(), finding.matched_content.clone());
+    let mut remaining: Vec<_> = before.iter().collect();
+    let mut changes = Vec::new();
+
+    for finding in after {
+        match remaining
+            .iter()
+            .position(|previous| key(previous) == key(finding))
+        {
+            Some(index) => {
+                remaining.remove(index);
+            }
+            None => changes.push(FindingChange::New(finding.clone())),
+        }
+    }
+    changes.extend(
+        remaining
+            .into_iter()
+            .map(|finding| FindingChange::Resolved(finding.clone())),
+    );
+    changes
+}
+
+/// Keeps the current findings of every watched file and rescans files as they change.
+/// Findings inside an archive are kept under the archive's own path, the file that changes.
+pub struct WatchSession {
+    scanner: Scanner,
+    roots: Vec<PathBuf>,
+    findings: BTreeMap<String, Vec<Finding>>,
+}
+
+impl WatchSession {
+    /// new scans `paths` once; the initial findings are returned by `findings`.
+    pub fn new(scanner: Scanner, paths: &[String]) -> error::Result<WatchSession> {
+        let roots = paths
+            .iter()
+            .map(|path| {
+                fs::canonicalize(path).map_err(|source| Error::Io {
+                    path: path.into(),
+                    source,
+                })
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+        let root_strings: Vec<String> = roots
+            .iter()
+            .map(|root| root.to_string_lossy().into_owned())
+            .collect();
+
+        let (initial, _) = scanner.scan_paths(&root_strings)?;
+        let mut findings: BTreeMap<String, Vec<Finding>> = BTreeMap::new();
+        for finding in initial {
+            findings
+                .entry(archive::outer_path(&finding.file_path).to_string())
+                .or_default()
+                .push(finding);
+        }
+
+        Ok(WatchSession {
+            scanner,
+            roots,
+            findings,
+        })
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// findings returns the current findings of every watched file.
+    pub fn findings(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.values().flatten()
+    }
+
+    /// rescan scans the changed paths again and returns how their findings changed. A
+    /// changed directory is rescanned in full; a deleted file resolves all its findings.
+    pub fn rescan(&mut self, changed: &BTreeSet<PathBuf>) -> Vec<FindingChange> {
+        let mut files = BTreeSet::new();
+        for path in changed {
+            if path.is_dir() {
+                let mut collected = Vec::new();
+                let dir = path.to_string_lossy();
+                collect_files(&dir, &mut collected, &dir);
+                files.extend(collected.into_iter().map(|(file, _)| file));
+            } else {
+                files.insert(path.to_string_lossy().into_owned());
+            }
+            // Files under a deleted directory are only known from earlier findings.
+            if !path.exists() {
+                files.extend(
+                    self.findings
+                        .keys()
+                        .filter(|file| Path::new(file).starts_with(path))
+                        .cloned(),
+                );
+            }
+        }
+
+        let mut changes = Vec::new();
+        for file in files {
+            let roots = self.roots_of(Path::new(&file));
+            let after = self.scanner.scan_file(&file, &roots).unwrap_or_default();
+            let before = self.findings.remove(&file).unwrap_or_default();
+            changes.extend(diff_findings(&before, &after));
+            if !after.is_empty() {
+                self.findings.insert(file, after);
+            }
+        }
+        changes
+    }
+
+    /// roots_of returns the watched directories containing `path`, in the form
+    /// `scan_paths` passes to exclude patterns.
+    fn roots_of(&self, path: &Path) -> Vec<Option<String>> {
+        let roots: Vec<Option<String>> = self
+            .roots
+            .iter()
+            .filter(|root| root.is_dir() && path.starts_with(root))
+            .map(|root| Some(root.to_string_lossy().into_owned()))
+            .collect();
+        if roots.is_empty() { vec![None] } else { roots }
+    }
+}
+
+/// Filesystem notifications for the watched paths.
+pub struct ChangeWatcher {
+    // Dropping the watcher stops the events.
+    _watcher: Box<dyn Watcher>,
+    events: EventReceiver,
+    warnings: Vec<String>,
+}
+
+impl ChangeWatcher {
+    /// start watches `roots` recursively. Native notifications are used unless `poll` is
+    /// set or they are unavailable, in which case the paths are polled every POLL_INTERVAL.
+    /// Falling back to polling is reported in `warnings`.
+    pub fn start(roots: &[PathBuf], poll: bool) -> error::Result<ChangeWatcher> {
+        let mut warnings = Vec::new();
+        let (watcher, events) = start_watcher(roots, poll, &mut warnings)?;
+        Ok(ChangeWatcher {
+            _watcher: watcher,
+            events,
+            warnings,
+        })
+    }
+
+    /// warnings lists problems met while starting, such as native notifications being
+    /// unavailable.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// run calls `on_batch` with the paths changed in each burst of events, until the
+    /// watcher stops. A burst ends once no event arrived for `debounce`, so an editor's
+    /// save sequence leads to one rescan. A burst lasts at most MAX_BURST_DEBOUNCES times
+    /// `debounce`, so a file written continuously, such as a log, is still rescanned.
+    /// Errors reported by the watcher are passed to `on_error` and do not stop it.
+    pub fn run(
+        self,
+        debounce: Duration,
+        mut on_batch: impl FnMut(BTreeSet<PathBuf>),
+        mut on_error: impl FnMut(notify::Error),
+    ) {
+        while let Ok(first) = self.events.recv() {
+            let mut changed = BTreeSet::new();
+            collect_event_paths(first, &mut changed, &mut on_error);
+            let deadline = Instant::now() + debounce * MAX_BURST_DEBOUNCES;
+            while let Some(wait) = deadline.checked_duration_since(Instant::now())
+                && let Ok(event) = self.events.recv_timeout(wait.min(debounce))
+            {
+                collect_event_paths(event, &mut changed, &mut on_error);
+            }
+            if !changed.is_empty() {
+                on_batch(changed);
+            }
+        }
+    }
+}
+
+type EventReceiver = Receiver<notify::Result<Event>>;
+
+fn start_watcher(
+    roots: &[PathBuf],
+    poll: bool,
+    warnings: &mut Vec<String>,
+) -> error::Result<(Box<dyn Watcher>, EventReceiver)> {
+    if !poll {
+        let (tx, rx) = channel();
+        let native = RecommendedWatcher::new(tx, Config::default())
+            .map(|watcher| Box::new(watcher) as Box<dyn Watcher>)
+            .and_then(|watcher| watch_roots(watcher, roots));
+        match native {
+            Ok(watcher) => return Ok((watcher, rx)),
+            Err(err) => warnings.push(format!(
+                "file notifications unavailable ({}); polling every {}s",
+                err,
+                POLL_INTERVAL.as_secs()
+            )),
+        }
+    }
+
+    let (tx, rx) = channel();
+    let polling = PollWatcher::new(tx, Config::default().with_poll_interval(POLL_INTERVAL))
+        .map(|watcher| Box::new(watcher) as Box<dyn Watcher>)
+        .and_then(|watcher| watch_roots(watcher, roots))
+        .map_err(|err| Error::Io {
+            path: roots.first().cloned().unwrap_or_default(),
+            source: std::io::Error::other(err),
+        })?;
+    Ok((polling, rx))
+}
+
+fn watch_roots(
+    mut watcher: Box<dyn Watcher>,
+    roots: &[PathBuf],
+) -> notify::Result<Box<dyn Watcher>> {
+    for root in roots {
+        watcher.watch(root, RecursiveMode::Recursive)?;
+    }
+    Ok(watcher)
+}
+
+fn collect_event_paths(
+    event: notify::Result<Event>,
+    changed: &mut BTreeSet<PathBuf>,
+    on_error: &mut impl FnMut(notify::Error),
+) {
+    match event {
+        Ok(event) if !event.kind.is_access() => changed.extend(event.paths),
+        Ok(_) => {}
+        Err(err) => on_error(err),
+    }
+}
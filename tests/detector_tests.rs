@@ -0,0 +1,293 @@
+use key_watch::detector::{Detector, default_detectors, load_detectors};
+use key_watch::report::Severity;
+use std::env::temp_dir;
+use std::fs;
+use std::path::PathBuf;
+
+fn write_config(name: &str, contents: &str) -> PathBuf {
+    let dir = temp_dir().join(format!(
+        "keywatch_{name}_{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    ));
+    fs::create_dir_all(&dir).expect("Create test dir");
+    let path = dir.join("detectors.toml");
+    fs::write(&path, contents).expect("Write detectors");
+    path
+}
+
+#[test]
+fn test_load_detectors_rejects_unknown_severity() {
+    let config_path = write_config(
+        "detector_unknown_severity",
+        r#"
+[[detectors]]
+name = "TokenDetector"
+pattern = "tok_[a-z]{8}"
+finding_type = "Token"
+severity = "HIHG"
+"#,
+    );
+
+    let error = match load_detectors(&config_path) {
+        Ok(_) => panic!("typo in severity should be rejected"),
+        Err(err) => err.to_string(),
+    };
+    assert!(
+        error.contains("Detector 'TokenDetector' has an unknown severity 'HIHG'"),
+        "Error should name the detector and value: {error}"
+    );
+
+    fs::remove_dir_all(config_path.parent().unwrap()).expect("Cleanup");
+}
+
+#[test]
+fn test_load_detectors_accepts_critical_and_info() {
+    let config_path = write_config(
+        "detector_critical_info",
+        r#"
+[[detectors]]
+name = "LiveKeyDetector"
+pattern = "live_[a-z]{8}"
+finding_type = "Live Key"
+severity = "CRITICAL"
+
+[[detectors]]
+name = "PrivateIPDetector"
+pattern = "\\b10\\.\\d+\\.\\d+\\.\\d+\\b"
+finding_type = "Private IP"
+severity = "info"
+"#,
+    );
+
+    let detectors = load_detectors(&config_path).expect("Should load detectors");
+    assert_eq!(detectors[0].severity, Severity::Critical);
+    assert_eq!(detectors[1].severity, Severity::Info);
+
+    fs::remove_dir_all(config_path.parent().unwrap()).expect("Cleanup");
+}
+
+#[test]
+fn test_detector_path_scoping_is_root_relative() {
+    let config_path = write_config(
+        "detector_path_scoping",
+        r#"
+[[detectors]]
+name = "EnvOnlyDetector"
+pattern = "SECRET=.+"
+finding_type = "Env Secret"
+severity = "HIGH"
+paths = ["**/*.env"]
+
+[[detectors]]
+name = "NotInDocsDetector"
+pattern = "token_[a-z]+"
+finding_type = "Token"
+severity = "LOW"
+exclude_paths = ["docs/**", "*.lock"]
+"#,
+    );
+
+    let detectors = load_detectors(&config_path).expect("Should load detectors");
+    let root = vec![Some("/repo".to_string())];
+    let env_only = &detectors[0];
+    let not_in_docs = &detectors[1];
+
+    assert!(env_only.applies_to("/repo/prod.env", &root));
+    assert!(env_only.applies_to("/repo/services/api/.env", &root));
+    assert!(!env_only.applies_to("/repo/src/main.rs", &root));
+
+    assert!(not_in_docs.applies_to("/repo/src/main.rs", &root));
+    assert!(!not_in_docs.applies_to("/repo/docs/guide/setup.md", &root));
+    assert!(!not_in_docs.applies_to("/repo/Cargo.lock", &root));
+    assert!(
+        not_in_docs.applies_to("/repo/src/docs/notes.md", &root),
+        "docs/** is anchored at the scan root"
+    );
+    assert!(
+        !not_in_docs.applies_to("docs/notes.md", &[None]),
+        "Relative paths without a root match directly"
+    );
+
+    fs::remove_dir_all(config_path.parent().unwrap()).expect("Cleanup");
+}
+
+#[test]
+fn test_load_detectors_rejects_invalid_path_pattern() {
+    let config_path = write_config(
+        "detector_invalid_path_pattern",
+        r#"
+[[detectors]]
+name = "TokenDetector"
+pattern = "tok_[a-z]{8}"
+finding_type = "Token"
+severity = "HIGH"
+paths = ["src/[unclosed"]
+"#,
+    );
+
+    let error = match load_detectors(&config_path) {
+        Ok(_) => panic!("invalid glob should be rejected"),
+        Err(err) => err.to_string(),
+    };
+    assert!(
+        error.contains("Detector 'TokenDetector' has an invalid path pattern 'src/[unclosed'"),
+        "Error should name the detector and pattern: {error}"
+    );
+
+    fs::remove_dir_all(config_path.parent().unwrap()).expect("Cleanup");
+}
+
+#[test]
+fn test_default_detectors_are_embedded() {
+    let detectors = default_detectors().expect("Built-in detectors should compile");
+    assert!(
+        detectors
+            .iter()
+            .any(|detector| detector.name == "AWSKeyDetector"),
+        "Built-in set should include the shipped detectors"
+    );
+}
+
+#[test]
+fn test_load_detectors_follows_extends_disable_and_overrides() {
+    let config_path = write_config(
+        "layered_config",
+        r#"
+extends = ["pack.toml"]
+disable = ["EmailDetector"]
+
+[[detectors]]
+name = "TokenDetector"
+severity = "CRITICAL"
+"#,
+    );
+    let dir = config_path.parent().unwrap();
+    fs::write(
+        dir.join("pack.toml"),
+        r#"
+[[detectors]]
+name = "TokenDetector"
+pattern = "tok_[a-z]{8}"
+finding_type = "Token"
+severity = "LOW"
+paths = ["src/**"]
+
+[[detectors]]
+name = "EmailDetector"
+pattern = "[a-z]+@[a-z]+\\.com"
+finding_type = "Email"
+severity = "LOW"
+"#,
+    )
+    .expect("Write rule pack");
+
+    let detectors = load_detectors(&config_path).expect("Should load layered detectors");
+    assert_eq!(detectors.len(), 1, "EmailDetector should be disabled");
+    assert_eq!(detectors[0].name, "TokenDetector");
+    assert_eq!(detectors[0].severity, Severity::Critical);
+    assert_eq!(detectors[0].regex.as_str(), "tok_[a-z]{8}");
+    assert_eq!(
+        detectors[0].paths.len(),
+        1,
+        "Fields not overridden are inherited"
+    );
+
+    fs::remove_dir_all(dir).expect("Cleanup");
+}
+
+#[test]
+fn test_load_detectors_rejects_bad_layering() {
+    let config_path = write_config("bad_layering", "extends = [\"detectors.toml\"]\n");
+    let error = match load_detectors(&config_path) {
+        Ok(_) => panic!("Circular extends should fail"),
+        Err(err) => err.to_string(),
+    };
+    assert!(error.contains("Circular extends"), "Got: {error}");
+
+    fs::write(&config_path, "disable = [\"NoSuchDetector\"]\n").expect("Write detectors");
+    let error = match load_detectors(&config_path) {
+        Ok(_) => panic!("Disabling an unknown detector should fail"),
+        Err(err) => err.to_string(),
+    };
+    assert!(
+        error.contains("unknown detector 'NoSuchDetector'"),
+        "Got: {error}"
+    );
+
+    fs::write(
+        &config_path,
+        "[[detectors]]\nname = \"PartialDetector\"\nseverity = \"LOW\"\n",
+    )
+    .expect("Write detectors");
+    let error = match load_detectors(&config_path) {
+        Ok(_) => panic!("A new detector without a pattern should fail"),
+        Err(err) => err.to_string(),
+    };
+    assert!(error.contains("needs `pattern`"), "Got: {error}");
+
+    fs::remove_dir_all(config_path.parent().unwrap()).expect("Cleanup");
+}
+
+#[test]
+fn test_default_detectors_have_stable_ids_and_guidance() {
+    let detectors = default_detectors().expect("Built-in detectors should compile");
+    let mut ids = std::collections::HashSet::new();
+
+    for detector in &detectors {
+        let metadata = &detector.metadata;
+        let id = metadata
+            .id
+            .as_deref()
+            .expect("Built-in detectors have an id");
+        assert!(
+            id.starts_with("KW") && id.len() == 6,
+            "{} has a malformed id {id}",
+            detector.name
+        );
+        assert!(ids.insert(id), "{id} is used twice");
+        assert!(!metadata.tags.is_empty(), "{} has no tags", detector.name);
+        assert!(
+            metadata.description.is_some() && metadata.remediation.is_some(),
+            "{} needs a description and remediation",
+            detector.name
+        );
+    }
+}
+
+#[test]
+fn test_load_detectors_reads_metadata() {
+    let config_path = write_config(
+        "detector_metadata",
+        r#"
+[[detectors]]
+name = "TokenDetector"
+id = "ACME001"
+pattern = "tok_[a-z]{8}"
+finding_type = "Token"
+severity = "HIGH"
+tags = ["credential"]
+description = "Internal service token"
+remediation = "Rotate it in the token service"
+references = ["https://wiki.example.com/tokens"]
+"#,
+    );
+
+    let detectors = load_detectors(&config_path).expect("Should load detectors");
+    let metadata = &detectors[0].metadata;
+    assert_eq!(metadata.id.as_deref(), Some("ACME001"));
+    assert_eq!(metadata.tags, ["credential"]);
+    assert_eq!(
+        metadata.description.as_deref(),
+        Some("Internal service token")
+    );
+    assert_eq!(
+        metadata.remediation.as_deref(),
+        Some("Rotate it in the token service")
+    );
+    assert_eq!(metadata.references, ["https://wiki.example.com/tokens"]);
+
+    fs::remove_dir_all(config_path.parent().unwrap()).expect("Cleanup");
+}
@@ -172,7 +172,7 @@ fn test_cli_scan_defaults_to_strict_exit_mode() {
 
     match options.command {
         Command::Scan(scan_args) => {
-            assert_eq!(scan_args.exit_mode, ExitMode::Strict);
+            assert_eq!(scan_args.policy.exit_mode, ExitMode::Strict);
         }
         _ => panic!("expected scan command"),
     }
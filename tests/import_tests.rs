@@ -0,0 +1,171 @@
+use key_watch::import::{import_gitleaks, regex_to_glob};
+use key_watch::report::Severity;
+use key_watch::rules::lint_detectors_toml;
+use std::env::temp_dir;
+use std::fs;
+use std::process::Command;
+
+const GITLEAKS_CONFIG: &str = r#"
+title = "in-house rules"
+
+[extend]
+useDefault = true
+
+[allowlist]
+paths = ['''(^|/)package-lock\.json$''', '''^vendor/.*$''']
+
+[[rules]]
+id = "acme-api-key"
+description = "ACME API Key"
+regex = '''\bacme_[a-z0-9]{32}\b'''
+keywords = ["acme_"]
+tags = ["credential", "acme"]
+entropy = 3.5
+secretGroup = 1
+path = '''\.env$'''
+[[rules.allowlists]]
+regexes = ['''acme_test''']
+paths = ['''\.md$''']
+
+[[rules]]
+id = "internal-token"
+regex = '''itk_[a-z0-9]{12}'''
+
+[[rules]]
+id = "pkcs12-file"
+path = '''\.p12$'''
+
+[[rules]]
+id = "lookbehind-rule"
+regex = '''(?<!x)abc'''
+"#;
+
+fn warnings_for<'a>(warnings: &'a [key_watch::import::ImportWarning], rule: &str) -> Vec<&'a str> {
+    warnings
+        .iter()
+        .filter(|warning| warning.rule == rule)
+        .map(|warning| warning.message.as_str())
+        .collect()
+}
+
+#[test]
+fn test_import_gitleaks_converts_rules() {
+    let result = import_gitleaks(GITLEAKS_CONFIG, Severity::Medium).expect("Should import");
+    assert_eq!(result.imported, 2);
+    assert_eq!(result.skipped, 2);
+
+    let document: toml::Table = toml::from_str(&result.detectors_toml).expect("Valid TOML");
+    let detectors = document["detectors"].as_array().expect("Detector list");
+    let acme = detectors[0].as_table().expect("Detector table");
+    assert_eq!(acme["name"].as_str(), Some("AcmeApiKeyDetector"));
+    assert_eq!(acme["id"].as_str(), Some("acme-api-key"));
+    assert_eq!(acme["tags"].as_array().unwrap().len(), 2);
+    assert_eq!(acme["pattern"].as_str(), Some(r"\bacme_[a-z0-9]{32}\b"));
+    assert_eq!(acme["finding_type"].as_str(), Some("ACME API Key"));
+    assert_eq!(acme["severity"].as_str(), Some("MEDIUM"));
+    assert_eq!(acme["paths"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        acme["exclude_paths"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(toml::Value::as_str)
+            .collect::<Vec<_>>(),
+        ["package-lock.json", "vendor/*", "*.md"]
+    );
+
+    let token = detectors[1].as_table().expect("Detector table");
+    assert_eq!(token["name"].as_str(), Some("InternalTokenDetector"));
+    assert_eq!(
+        token["finding_type"].as_str(),
+        Some("internal-token"),
+        "Rules without a description fall back to their id"
+    );
+
+    let issues = lint_detectors_toml(&result.detectors_toml).expect("Should lint");
+    assert!(
+        issues.is_empty(),
+        "Imported detectors should lint cleanly: {issues:?}"
+    );
+}
+
+#[test]
+fn test_import_gitleaks_warns_about_unsupported_constructs() {
+    let result = import_gitleaks(GITLEAKS_CONFIG, Severity::High).expect("Should import");
+
+    assert!(
+        warnings_for(&result.warnings, "<file>")
+            .iter()
+            .any(|message| message.contains("[extend] is ignored"))
+    );
+
+    let acme = warnings_for(&result.warnings, "acme-api-key");
+    for construct in [
+        "secretGroup = 1",
+        "entropy = 3.5",
+        "keywords",
+        "allowlist regexes",
+    ] {
+        assert!(
+            acme.iter().any(|message| message.contains(construct)),
+            "Expected a warning about {construct}: {acme:?}"
+        );
+    }
+
+    assert!(warnings_for(&result.warnings, "pkcs12-file")[0].contains("path-only"));
+    assert!(warnings_for(&result.warnings, "lookbehind-rule")[0].contains("does not compile"));
+    assert!(warnings_for(&result.warnings, "internal-token").is_empty());
+}
+
+#[test]
+fn test_regex_to_glob() {
+    assert_eq!(regex_to_glob(r"\.lock$").as_deref(), Some("*.lock"));
+    assert_eq!(regex_to_glob(r"^vendor/.*$").as_deref(), Some("vendor/*"));
+    assert_eq!(
+        regex_to_glob(r"(^|/)package-lock\.json$").as_deref(),
+        Some("package-lock.json")
+    );
+    assert_eq!(regex_to_glob(r"(?i)\.lock$"), None);
+    assert_eq!(regex_to_glob(r"\.(js|ts)$"), None);
+    assert_eq!(
+        regex_to_glob(r"vendor/"),
+        None,
+        "Unanchored prefixes have no glob form"
+    );
+}
+
+#[test]
+fn test_rules_import_command_writes_detectors() {
+    let test_dir = temp_dir().join(format!(
+        "keywatch_rules_import_{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    ));
+    fs::create_dir_all(&test_dir).expect("Create test dir");
+    let gitleaks_path = test_dir.join("gitleaks.toml");
+    let output_path = test_dir.join("detectors.toml");
+    fs::write(&gitleaks_path, GITLEAKS_CONFIG).expect("Write gitleaks config");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_key-watch"))
+        .args(["rules", "import", "--from", "gitleaks"])
+        .arg(&gitleaks_path)
+        .arg("--output")
+        .arg(&output_path)
+        .output()
+        .expect("Run rules import");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr.contains("WARNING  acme-api-key: entropy = 3.5 is ignored"));
+    assert!(stderr.contains("Imported 2 rule(s), skipped 2"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_key-watch"))
+        .args(["rules", "lint", "--config"])
+        .arg(&output_path)
+        .output()
+        .expect("Run rules lint");
+    assert_eq!(output.status.code(), Some(0), "Imported file should lint");
+
+    fs::remove_dir_all(test_dir).expect("Cleanup");
+}
@@ -26,8 +26,13 @@ fn test_write_to_file() {
 
 #[test]
 fn test_portable_config_loading() {
+    use key_watch::config::DetectorSelection;
     use key_watch::detector::initialize_detectors;
 
-    let detectors = initialize_detectors().expect("Should load detectors");
-    assert!(!detectors.is_empty(), "Should load at least one detector");
+    let configured =
+        initialize_detectors(None, &DetectorSelection::default()).expect("Should load detectors");
+    assert!(
+        !configured.detectors.is_empty(),
+        "Should load at least one detector"
+    );
 }